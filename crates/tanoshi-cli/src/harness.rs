@@ -0,0 +1,379 @@
+use std::{
+    future::Future,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+use tanoshi_lib::prelude::{ChapterInfo, Input, MangaInfo, SourceInfo};
+use tanoshi_vm::prelude::ExtensionManager;
+
+/// Result of a single extension call
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    pub duration_ms: u128,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub source_id: i64,
+    pub source_name: String,
+    pub version: String,
+    pub passed: bool,
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    pub fn print(&self) {
+        println!(
+            "{} {} (id {})",
+            self.source_name, self.version, self.source_id
+        );
+        for check in &self.checks {
+            let status = if check.passed { "PASS" } else { "FAIL" };
+            match &check.message {
+                Some(message) => println!(
+                    "[{status}] {} ({}ms): {message}",
+                    check.name, check.duration_ms
+                ),
+                None => println!("[{status}] {} ({}ms)", check.name, check.duration_ms),
+            }
+        }
+
+        let failed = self.checks.iter().filter(|check| !check.passed).count();
+        println!("{} passed, {} failed", self.checks.len() - failed, failed);
+    }
+}
+
+#[derive(Default)]
+struct Checks(Vec<Check>);
+
+impl Checks {
+    /// Run `f` and record the outcome, returns the value if it passed validation
+    async fn check<T, F, V>(&mut self, name: String, f: F, validate: V) -> Option<T>
+    where
+        F: Future<Output = Result<T>>,
+        V: FnOnce(&T) -> Result<()>,
+    {
+        let start = Instant::now();
+        let res = f.await.and_then(|value| validate(&value).map(|_| value));
+        let duration = start.elapsed();
+
+        self.record(name, res.as_ref().err().map(|e| e.to_string()), duration);
+        res.ok()
+    }
+
+    fn record(&mut self, name: String, error: Option<String>, duration: Duration) {
+        self.0.push(Check {
+            name,
+            passed: error.is_none(),
+            message: error,
+            duration_ms: duration.as_millis(),
+        });
+    }
+
+    fn skip(&mut self, name: &str, reason: &str) {
+        self.record(
+            name.to_string(),
+            Some(format!("skipped, {reason}")),
+            Duration::ZERO,
+        );
+    }
+
+    fn into_report(self, source: &SourceInfo) -> Report {
+        Report {
+            source_id: source.id,
+            source_name: source.name.clone(),
+            version: source.version.to_string(),
+            passed: self.0.iter().all(|check| check.passed),
+            checks: self.0,
+        }
+    }
+}
+
+// pages and covers are fetched by the server, so they have to be absolute http urls
+fn is_http_url(url: &str) -> bool {
+    match url.split_once("://") {
+        Some((scheme, rest)) => {
+            (scheme == "http" || scheme == "https")
+                && !rest.starts_with('/')
+                && !rest.trim().is_empty()
+        }
+        None => false,
+    }
+}
+
+fn validate_manga(source_id: i64, manga: &MangaInfo) -> Result<()> {
+    if manga.source_id != source_id {
+        bail!(
+            "{:?} has source_id {}, expected {source_id}",
+            manga.title,
+            manga.source_id
+        );
+    }
+    if manga.title.trim().is_empty() {
+        bail!("manga {:?} has empty title", manga.path);
+    }
+    if manga.path.trim().is_empty() {
+        bail!("{:?} has empty path", manga.title);
+    }
+    // a source may have no cover
+    if !manga.cover_url.is_empty() && !is_http_url(&manga.cover_url) {
+        bail!(
+            "{:?} has invalid cover_url {:?}",
+            manga.title,
+            manga.cover_url
+        );
+    }
+
+    Ok(())
+}
+
+fn validate_manga_list(source_id: i64, manga: &[MangaInfo], allow_empty: bool) -> Result<()> {
+    if manga.is_empty() && !allow_empty {
+        bail!("no manga returned");
+    }
+
+    manga
+        .iter()
+        .try_for_each(|manga| validate_manga(source_id, manga))
+}
+
+fn validate_chapter(source_id: i64, chapter: &ChapterInfo) -> Result<()> {
+    if chapter.source_id != source_id {
+        bail!(
+            "{:?} has source_id {}, expected {source_id}",
+            chapter.title,
+            chapter.source_id
+        );
+    }
+    if chapter.path.trim().is_empty() {
+        bail!("{:?} has empty path", chapter.title);
+    }
+    if !chapter.number.is_finite() || chapter.number < 0.0 {
+        bail!("{:?} has invalid number {}", chapter.title, chapter.number);
+    }
+
+    Ok(())
+}
+
+fn validate_pages(pages: &[String]) -> Result<()> {
+    if pages.is_empty() {
+        bail!("no pages returned");
+    }
+    if let Some(index) = pages.iter().position(|page| page.trim().is_empty()) {
+        bail!("page {index} is empty");
+    }
+    if let Some(page) = pages.iter().find(|page| !is_http_url(page)) {
+        bail!("page {page:?} is not an http url");
+    }
+
+    Ok(())
+}
+
+/// Load extension at `path` and call each method in sequence,
+/// output of a call is used as input of the next one
+pub async fn run(path: &Path, query: Option<String>) -> Result<Report> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow!("invalid extension path {}", path.display()))?;
+
+    let manager = ExtensionManager::new(dir);
+    manager.load(file_name).await?;
+    let source = manager
+        .list()
+        .await?
+        .pop()
+        .ok_or_else(|| anyhow!("{} has no extension", path.display()))?;
+
+    let source_id = source.id;
+    let mut checks = Checks::default();
+
+    let popular = checks
+        .check(
+            "get_popular_manga".to_string(),
+            manager.get_popular_manga(source_id, 1),
+            |manga| validate_manga_list(source_id, manga, false),
+        )
+        .await;
+
+    checks
+        .check(
+            "get_latest_manga".to_string(),
+            manager.get_latest_manga(source_id, 1),
+            |manga| validate_manga_list(source_id, manga, false),
+        )
+        .await;
+
+    if let Some(query) = query.or_else(|| {
+        popular
+            .as_ref()
+            .and_then(|manga| manga.first())
+            .map(|manga| manga.title.clone())
+    }) {
+        checks
+            .check(
+                format!("search_manga query {query:?}"),
+                manager.search_manga(source_id, 1, Some(query.clone()), None),
+                |manga| validate_manga_list(source_id, manga, false),
+            )
+            .await;
+    }
+
+    let filters: Vec<Input> = manager.filter_list(source_id)?;
+    for filter in filters {
+        // a single filter may legitimately narrow down to nothing
+        checks
+            .check(
+                format!("search_manga filter {:?}", filter.name()),
+                manager.search_manga(source_id, 1, None, Some(vec![filter])),
+                |manga| validate_manga_list(source_id, manga, true),
+            )
+            .await;
+    }
+
+    let manga = match popular.and_then(|mut manga| (!manga.is_empty()).then(|| manga.remove(0))) {
+        Some(manga) => manga,
+        None => {
+            checks.skip("get_manga_detail", "no manga from get_popular_manga");
+            return Ok(checks.into_report(&source));
+        }
+    };
+
+    checks
+        .check(
+            format!("get_manga_detail {:?}", manga.path),
+            manager.get_manga_detail(source_id, manga.path.clone()),
+            |manga| validate_manga(source_id, manga),
+        )
+        .await;
+
+    let chapters = checks
+        .check(
+            format!("get_chapters {:?}", manga.path),
+            manager.get_chapters(source_id, manga.path.clone()),
+            |chapters: &Vec<ChapterInfo>| {
+                if chapters.is_empty() {
+                    bail!("no chapters returned");
+                }
+                chapters
+                    .iter()
+                    .try_for_each(|chapter| validate_chapter(source_id, chapter))
+            },
+        )
+        .await;
+
+    match chapters.and_then(|chapters| chapters.into_iter().next()) {
+        Some(chapter) => {
+            checks
+                .check(
+                    format!("get_pages {:?}", chapter.path),
+                    manager.get_pages(source_id, chapter.path.clone()),
+                    |pages| validate_pages(pages),
+                )
+                .await;
+        }
+        None => checks.skip("get_pages", "no chapter from get_chapters"),
+    }
+
+    Ok(checks.into_report(&source))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn manga(title: &str, path: &str) -> MangaInfo {
+        MangaInfo {
+            source_id: 1,
+            title: title.to_string(),
+            author: vec![],
+            genre: vec![],
+            status: None,
+            description: None,
+            path: path.to_string(),
+            cover_url: "https://example.com/cover.jpg".to_string(),
+        }
+    }
+
+    fn chapter(path: &str, number: f64) -> ChapterInfo {
+        ChapterInfo {
+            source_id: 1,
+            title: "Chapter".to_string(),
+            path: path.to_string(),
+            number,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_is_http_url() {
+        assert!(is_http_url("https://example.com/1.jpg"));
+        assert!(is_http_url("http://example.com"));
+        assert!(!is_http_url("/images/1.jpg"));
+        assert!(!is_http_url("example.com/1.jpg"));
+        assert!(!is_http_url("ftp://example.com/1.jpg"));
+        assert!(!is_http_url("https://"));
+        assert!(!is_http_url("https:///1.jpg"));
+    }
+
+    #[test]
+    fn test_validate_manga() {
+        assert!(validate_manga(1, &manga("Title", "/manga/1")).is_ok());
+        assert!(validate_manga(2, &manga("Title", "/manga/1")).is_err());
+        assert!(validate_manga(1, &manga(" ", "/manga/1")).is_err());
+        assert!(validate_manga(1, &manga("Title", "")).is_err());
+
+        let mut no_cover = manga("Title", "/manga/1");
+        no_cover.cover_url = String::new();
+        assert!(validate_manga(1, &no_cover).is_ok());
+
+        let mut relative_cover = manga("Title", "/manga/1");
+        relative_cover.cover_url = "/cover.jpg".to_string();
+        assert!(validate_manga(1, &relative_cover).is_err());
+    }
+
+    #[test]
+    fn test_validate_manga_list() {
+        assert!(validate_manga_list(1, &[], false).is_err());
+        assert!(validate_manga_list(1, &[], true).is_ok());
+        assert!(validate_manga_list(1, &[manga("Title", "/manga/1")], false).is_ok());
+        assert!(
+            validate_manga_list(1, &[manga("Title", "/manga/1"), manga("Title", "")], false)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_validate_chapter() {
+        assert!(validate_chapter(1, &chapter("/chapter/1", 1.0)).is_ok());
+        assert!(validate_chapter(1, &chapter("/chapter/0", 0.0)).is_ok());
+        assert!(validate_chapter(2, &chapter("/chapter/1", 1.0)).is_err());
+        assert!(validate_chapter(1, &chapter("", 1.0)).is_err());
+        assert!(validate_chapter(1, &chapter("/chapter/1", -1.0)).is_err());
+        assert!(validate_chapter(1, &chapter("/chapter/1", f64::NAN)).is_err());
+        assert!(validate_chapter(1, &chapter("/chapter/1", f64::INFINITY)).is_err());
+    }
+
+    #[test]
+    fn test_validate_pages() {
+        let pages = |pages: &[&str]| {
+            pages
+                .iter()
+                .map(|page| page.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert!(validate_pages(&pages(&["https://example.com/1.jpg"])).is_ok());
+        assert!(validate_pages(&[]).is_err());
+        assert!(validate_pages(&pages(&["https://example.com/1.jpg", " "])).is_err());
+        assert!(validate_pages(&pages(&["https://example.com/1.jpg", "/2.jpg"])).is_err());
+    }
+}
@@ -1,11 +1,16 @@
 extern crate log;
 
-use std::path::PathBuf;
+mod harness;
+
+use std::{collections::HashMap, path::PathBuf};
 
 use clap::{Parser, Subcommand};
 use serde::Serialize;
 use tanoshi_lib::prelude::SourceInfo;
-use tanoshi_vm::{prelude::ExtensionManager, PLUGIN_EXTENSION};
+use tanoshi_vm::{
+    prelude::{decode_keypair, ExtensionManager, FileIndex},
+    PLUGIN_EXTENSION, WASM_EXTENSION, WASM_TARGET,
+};
 
 const TARGET: &str = env!("TARGET");
 
@@ -21,7 +26,22 @@ struct Opts {
 #[derive(Subcommand)]
 enum Command {
     /// Generate index.json
-    GenerateJson,
+    GenerateJson {
+        /// File containing base64 encoded ed25519 secret key to sign extensions with
+        #[clap(long)]
+        signing_key: Option<PathBuf>,
+    },
+    /// Call every method of an extension and validate the results
+    Test {
+        /// Path to extension library or wasm module
+        file: PathBuf,
+        /// Query for search_manga, defaults to title of first popular manga
+        #[clap(long)]
+        query: Option<String>,
+        /// Print report as JSON
+        #[clap(long)]
+        json: bool,
+    },
 }
 
 #[derive(Debug, Serialize)]
@@ -30,6 +50,9 @@ struct SourceIndex {
     source: SourceInfo,
     rustc_version: String,
     lib_version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    abi_version: Option<u32>,
+    files: HashMap<String, FileIndex>,
 }
 
 #[tokio::main]
@@ -39,9 +62,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let opts: Opts = Opts::parse();
 
     match opts.subcmd {
-        Command::GenerateJson => {
+        Command::GenerateJson { signing_key } => {
+            let keypair = match signing_key {
+                Some(path) => Some(decode_keypair(&tokio::fs::read_to_string(path).await?)?),
+                None => None,
+            };
+
             let target_dir_path = PathBuf::new().join("output").join(TARGET);
             tokio::fs::create_dir_all(&target_dir_path).await?;
+            let wasm_dir_path = PathBuf::new().join("output").join(WASM_TARGET);
+            tokio::fs::create_dir_all(&wasm_dir_path).await?;
 
             let mut read_dir = tokio::fs::read_dir(&opts.path).await?;
             while let Some(entry) = read_dir.next_entry().await? {
@@ -54,26 +84,75 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     let name = name.replace("lib", "");
 
                     tokio::fs::copy(entry.path(), &target_dir_path.join(name).as_path()).await?;
+                } else if name.ends_with(WASM_EXTENSION) {
+                    tokio::fs::copy(entry.path(), &wasm_dir_path.join(name).as_path()).await?;
                 }
             }
 
-            let extension_manager = ExtensionManager::new(&target_dir_path);
-            extension_manager.load_all().await?;
-            let source_list = extension_manager.list().await?;
-
-            let mut indexes = vec![];
-            for source in source_list {
-                let (rustc_version, lib_version) = extension_manager.get_version(source.id)?;
-                indexes.push(SourceIndex {
-                    source,
-                    rustc_version,
-                    lib_version,
-                })
+            let mut indexes: Vec<SourceIndex> = vec![];
+            for (target, dir_path, extension) in [
+                (TARGET, &target_dir_path, PLUGIN_EXTENSION),
+                (WASM_TARGET, &wasm_dir_path, WASM_EXTENSION),
+            ] {
+                let extension_manager = ExtensionManager::new(dir_path);
+                extension_manager.load_all().await?;
+                let source_list = extension_manager.list().await?;
+
+                for source in source_list {
+                    let contents = tokio::fs::read(
+                        dir_path
+                            .join(source.name.to_lowercase())
+                            .with_extension(extension),
+                    )
+                    .await?;
+                    let file = FileIndex::new(
+                        &source.name,
+                        &source.version,
+                        target,
+                        &contents,
+                        keypair.as_ref(),
+                    );
+
+                    // wasm build of a source that also has a native build
+                    if let Some(index) = indexes
+                        .iter_mut()
+                        .find(|index| index.source.id == source.id)
+                    {
+                        index.files.insert(target.to_string(), file);
+                        continue;
+                    }
+
+                    let (rustc_version, lib_version) = extension_manager.get_version(source.id)?;
+                    let abi_version = extension_manager.get_abi_version(source.id)?;
+
+                    let mut files = HashMap::new();
+                    files.insert(target.to_string(), file);
+
+                    indexes.push(SourceIndex {
+                        source,
+                        rustc_version,
+                        lib_version,
+                        abi_version,
+                        files,
+                    })
+                }
             }
 
             let json = serde_json::to_string(&indexes)?;
             tokio::fs::write(target_dir_path.join("index").with_extension("json"), json).await?;
         }
+        Command::Test { file, query, json } => {
+            let report = harness::run(&file, query).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                report.print();
+            }
+
+            if !report.passed {
+                std::process::exit(1);
+            }
+        }
     }
 
     Ok(())
@@ -53,6 +53,9 @@ pub trait PluginRegistrar {
 }
 
 /// macro for export an extension
+///
+/// Both `plugin_declaration` and the stable `tanoshi_plugin_declaration` are exported,
+/// host with support for `ffi::ABI_VERSION` will prefer the latter.
 #[macro_export]
 macro_rules! export_plugin {
     ($register:expr) => {
@@ -64,5 +67,15 @@ macro_rules! export_plugin {
                 core_version: $crate::LIB_VERSION,
                 register: $register,
             };
+
+        #[doc(hidden)]
+        #[no_mangle]
+        pub extern "C" fn tanoshi_plugin_declaration() -> $crate::ffi::PluginDeclarationFfi {
+            unsafe extern "C" fn create() -> *mut std::ffi::c_void {
+                $crate::ffi::create_extension($register)
+            }
+
+            $crate::ffi::PluginDeclarationFfi::new(create)
+        }
     };
 }
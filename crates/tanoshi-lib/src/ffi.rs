@@ -0,0 +1,204 @@
+//! Stable plugin interface.
+//!
+//! `PluginDeclaration` hands a `Box<dyn Extension>` to the host, which only works when both
+//! sides are built by the same `rustc`. The types in this module are `repr(C)` and only use
+//! `extern "C"` functions, every value crossing the boundary is encoded as JSON, so a plugin
+//! keeps loading as long as `ABI_VERSION` matches and its `core_version` is semver compatible.
+
+use std::{
+    ffi::c_void,
+    panic::{catch_unwind, AssertUnwindSafe},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::extensions::{Extension, PluginRegistrar};
+
+/// Bumped on every breaking change to the types in this module
+pub const ABI_VERSION: u32 = 1;
+
+/// Symbol exported by `export_plugin!` returning a `PluginDeclarationFfi`
+pub const DECLARATION_SYMBOL: &[u8] = b"tanoshi_plugin_declaration\0";
+
+/// Borrowed utf-8 string
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FfiStr {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+impl FfiStr {
+    pub fn new(s: &str) -> Self {
+        Self {
+            ptr: s.as_ptr(),
+            len: s.len(),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` and `len` must come from a `&str` that is still alive
+    pub unsafe fn as_str<'a>(&self) -> &'a str {
+        std::str::from_utf8_unchecked(std::slice::from_raw_parts(self.ptr, self.len))
+    }
+}
+
+/// Owned bytes allocated by the plugin, must be released with `ExtensionVTable::free_buffer`
+#[repr(C)]
+pub struct FfiBuffer {
+    pub ptr: *mut u8,
+    pub len: usize,
+    pub cap: usize,
+}
+
+impl FfiBuffer {
+    pub fn from_vec(data: Vec<u8>) -> Self {
+        let mut data = std::mem::ManuallyDrop::new(data);
+        Self {
+            ptr: data.as_mut_ptr(),
+            len: data.len(),
+            cap: data.capacity(),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The buffer must not be freed yet
+    pub unsafe fn as_slice(&self) -> &[u8] {
+        std::slice::from_raw_parts(self.ptr, self.len)
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ExtensionVTable {
+    /// Call `method` with JSON encoded arguments, returns JSON encoded `Result<T, String>`
+    pub call:
+        unsafe extern "C" fn(extension: *mut c_void, method: FfiStr, args: FfiStr) -> FfiBuffer,
+    pub free_buffer: unsafe extern "C" fn(buffer: FfiBuffer),
+    pub drop: unsafe extern "C" fn(extension: *mut c_void),
+}
+
+/// A type represents an extension using the stable interface
+#[repr(C)]
+pub struct PluginDeclarationFfi {
+    pub abi_version: u32,
+    pub rustc_version: FfiStr,
+    pub core_version: FfiStr,
+    /// Returns null if the plugin failed to register an extension
+    pub create: unsafe extern "C" fn() -> *mut c_void,
+    pub vtable: ExtensionVTable,
+}
+
+impl PluginDeclarationFfi {
+    pub fn new(create: unsafe extern "C" fn() -> *mut c_void) -> Self {
+        Self {
+            abi_version: ABI_VERSION,
+            rustc_version: FfiStr::new(crate::RUSTC_VERSION),
+            core_version: FfiStr::new(crate::LIB_VERSION),
+            create,
+            vtable: ExtensionVTable {
+                call: extension_call,
+                free_buffer: extension_free_buffer,
+                drop: extension_drop,
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+struct Registrar(Option<Box<dyn Extension>>);
+
+impl PluginRegistrar for Registrar {
+    fn register_function(&mut self, extension: Box<dyn Extension>) {
+        self.0 = Some(extension);
+    }
+}
+
+/// Run `register` and move the registered extension behind an opaque pointer
+///
+/// # Safety
+///
+/// `register` is the function passed to `export_plugin!`
+pub unsafe fn create_extension(register: unsafe fn(&mut dyn PluginRegistrar)) -> *mut c_void {
+    let extension = catch_unwind(|| {
+        let mut registrar = Registrar::default();
+        register(&mut registrar);
+        registrar.0
+    });
+
+    match extension {
+        Ok(Some(extension)) => Box::into_raw(Box::new(extension)) as *mut c_void,
+        _ => std::ptr::null_mut(),
+    }
+}
+
+fn respond<T: Serialize>(res: anyhow::Result<T>) -> Vec<u8> {
+    serde_json::to_vec(&res.map_err(|e| e.to_string())).unwrap_or_default()
+}
+
+fn args<T: DeserializeOwned>(args: &str) -> anyhow::Result<T> {
+    Ok(serde_json::from_str(args)?)
+}
+
+unsafe fn dispatch(extension: *mut Box<dyn Extension>, method: &str, input: &str) -> Vec<u8> {
+    let ext = &*extension;
+    match method {
+        "get_source_info" => respond(Ok(ext.get_source_info())),
+        "headers" => respond(Ok(ext.headers())),
+        "filter_list" => respond(Ok(ext.filter_list())),
+        "get_preferences" => respond(ext.get_preferences()),
+        // the host holds an exclusive borrow while calling this
+        "set_preferences" => {
+            respond(args(input).and_then(|preferences| (*extension).set_preferences(preferences)))
+        }
+        "get_popular_manga" => respond(args(input).and_then(|page| ext.get_popular_manga(page))),
+        "get_latest_manga" => respond(args(input).and_then(|page| ext.get_latest_manga(page))),
+        "search_manga" => respond(
+            args(input).and_then(|(page, query, filters)| ext.search_manga(page, query, filters)),
+        ),
+        "get_manga_detail" => respond(args(input).and_then(|path| ext.get_manga_detail(path))),
+        "get_chapters" => respond(args(input).and_then(|path| ext.get_chapters(path))),
+        "get_pages" => respond(args(input).and_then(|path| ext.get_pages(path))),
+        _ => respond::<()>(Err(anyhow::anyhow!("unknown method {method}"))),
+    }
+}
+
+unsafe fn dispatch_catch_unwind(
+    extension: *mut Box<dyn Extension>,
+    method: &str,
+    args: &str,
+) -> Vec<u8> {
+    catch_unwind(AssertUnwindSafe(|| dispatch(extension, method, args)))
+        .unwrap_or_else(|_| respond::<()>(Err(anyhow::anyhow!("extension panicked on {method}"))))
+}
+
+/// Call `method` with JSON encoded arguments, returns JSON encoded `Result<T, String>`.
+///
+/// This is the same protocol used by `ExtensionVTable::call`, for hosts that own the extension
+pub fn call_method(extension: &mut Box<dyn Extension>, method: &str, args: &str) -> Vec<u8> {
+    unsafe { dispatch_catch_unwind(extension, method, args) }
+}
+
+unsafe extern "C" fn extension_call(
+    extension: *mut c_void,
+    method: FfiStr,
+    args: FfiStr,
+) -> FfiBuffer {
+    FfiBuffer::from_vec(dispatch_catch_unwind(
+        extension as *mut Box<dyn Extension>,
+        method.as_str(),
+        args.as_str(),
+    ))
+}
+
+unsafe extern "C" fn extension_free_buffer(buffer: FfiBuffer) {
+    drop(Vec::from_raw_parts(buffer.ptr, buffer.len, buffer.cap));
+}
+
+unsafe extern "C" fn extension_drop(extension: *mut c_void) {
+    if !extension.is_null() {
+        drop(Box::from_raw(extension as *mut Box<dyn Extension>));
+    }
+}
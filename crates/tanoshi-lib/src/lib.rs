@@ -1,5 +1,6 @@
 pub mod error;
 pub mod extensions;
+pub mod ffi;
 pub mod models;
 pub mod prelude;
 
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 /// A type represent chapter, normalized across source
 ///
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct ChapterInfo {
     pub source_id: i64,
     pub title: String,
@@ -9,4 +9,13 @@ pub struct ChapterInfo {
     pub number: f64,
     pub scanlator: Option<String>,
     pub uploaded: i64,
+    /// Volume of the chapter, if known
+    #[serde(default)]
+    pub volume: Option<f64>,
+    /// Last chapter number when the chapter covers a range, e.g. 11 for chapter 10-11
+    #[serde(default)]
+    pub number_end: Option<f64>,
+    /// Extra, omake or side story outside of the main numbering
+    #[serde(default)]
+    pub extra: bool,
 }
@@ -14,8 +14,8 @@ pub struct SourceInfo {
     pub id: i64,
     pub name: String,
     pub url: String,
-    pub version: &'static str,
-    pub icon: &'static str,
+    pub version: String,
+    pub icon: String,
     pub languages: Lang,
     pub nsfw: bool,
 }
@@ -30,6 +30,30 @@ impl FromStr for Version {
     }
 }
 
+impl Version {
+    /// Check if an extension built against `required` can be loaded by `self`, using the same
+    /// rule as cargo's caret requirement: `0.x.y` is only compatible with `0.x.z` where `z >= y`,
+    /// otherwise major version has to match and `self` can't be older than `required`
+    pub fn is_compatible_with(&self, required: &Version) -> bool {
+        if self.major != required.major {
+            return false;
+        }
+
+        if self.major == 0 {
+            return self.minor == required.minor && self.patch >= required.patch;
+        }
+
+        (self.minor, self.patch) >= (required.minor, required.patch)
+    }
+
+    /// Check if an extension built against `required` can be loaded by `self` when their data
+    /// is serialized, as with `tanoshi_lib::ffi`, wasm and worker extensions. Fields added in a
+    /// minor version are `#[serde(default)]`, so only major version has to match
+    pub fn is_serde_compatible_with(&self, required: &Version) -> bool {
+        self.major == required.major
+    }
+}
+
 impl std::fmt::Display for Version {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_fmt(format_args!("{}.{}.{}", self.major, self.minor, self.patch))
@@ -81,3 +105,74 @@ where
 
     deserializer.deserialize_any(StringOrStruct(PhantomData))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_compatible_with() {
+        let cases = [
+            // 0.x only accepts same minor with older or same patch
+            ("0.27.0", "0.27.0", true),
+            ("0.27.3", "0.27.1", true),
+            ("0.27.1", "0.27.3", false),
+            ("0.27.0", "0.26.0", false),
+            ("0.27.0", "0.28.0", false),
+            ("0.0.1", "0.0.1", true),
+            // 1.x accepts any older or same minor and patch of same major
+            ("1.2.3", "1.2.3", true),
+            ("1.2.3", "1.2.0", true),
+            ("1.2.3", "1.0.9", true),
+            ("1.2.3", "1.2.4", false),
+            ("1.2.3", "1.3.0", false),
+            ("2.0.0", "1.9.9", false),
+            ("1.0.0", "0.27.0", false),
+        ];
+
+        for (host, required, compatible) in cases {
+            let host = Version::from_str(host).unwrap();
+            let required = Version::from_str(required).unwrap();
+            assert_eq!(
+                host.is_compatible_with(&required),
+                compatible,
+                "{host} with {required}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_serde_compatible_with() {
+        let cases = [
+            ("0.28.0", "0.27.0", true),
+            ("0.28.0", "0.28.1", true),
+            ("1.2.3", "1.5.0", true),
+            ("1.0.0", "0.28.0", false),
+            ("2.0.0", "1.9.9", false),
+        ];
+
+        for (host, required, compatible) in cases {
+            let host = Version::from_str(host).unwrap();
+            let required = Version::from_str(required).unwrap();
+            assert_eq!(
+                host.is_serde_compatible_with(&required),
+                compatible,
+                "{host} with {required}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(
+            Version::from_str("0.27.1").unwrap(),
+            Version {
+                major: 0,
+                minor: 27,
+                patch: 1
+            }
+        );
+        assert!(Version::from_str("0.27").is_err());
+        assert!(Version::from_str("0.27.x").is_err());
+    }
+}
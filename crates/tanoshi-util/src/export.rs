@@ -0,0 +1,165 @@
+/// Export an extension as a WebAssembly module.
+///
+/// Every `Extension` method becomes an exported function without parameters. Arguments are
+/// read from stdin and the result is written to stdout as `Result<T, String>`, both in RON,
+/// which is what the wasm host in `tanoshi-vm` expects. The extension type must implement
+/// `Default`, it is constructed once when the module is instantiated.
+#[macro_export]
+macro_rules! export_extension {
+    ($extension:ty) => {
+        thread_local! {
+            static EXTENSION: std::cell::RefCell<$extension> =
+                std::cell::RefCell::new(<$extension as Default>::default());
+        }
+
+        #[no_mangle]
+        pub extern "C" fn get_lib_version() {
+            $crate::shim::write_result(Ok::<_, String>(::tanoshi_lib::LIB_VERSION));
+        }
+
+        #[no_mangle]
+        pub extern "C" fn get_source_info() {
+            EXTENSION.with(|ext| {
+                let info = <$extension as ::tanoshi_lib::extensions::Extension>::get_source_info(
+                    &*ext.borrow(),
+                );
+                $crate::shim::write_result(Ok::<_, String>(info));
+            });
+        }
+
+        #[no_mangle]
+        pub extern "C" fn headers() {
+            EXTENSION.with(|ext| {
+                let headers =
+                    <$extension as ::tanoshi_lib::extensions::Extension>::headers(&*ext.borrow());
+                $crate::shim::write_result(Ok::<_, String>(headers));
+            });
+        }
+
+        #[no_mangle]
+        pub extern "C" fn filter_list() {
+            EXTENSION.with(|ext| {
+                let filters = <$extension as ::tanoshi_lib::extensions::Extension>::filter_list(
+                    &*ext.borrow(),
+                );
+                $crate::shim::write_result(Ok::<_, String>(filters));
+            });
+        }
+
+        #[no_mangle]
+        pub extern "C" fn get_preferences() {
+            EXTENSION.with(|ext| {
+                let res = <$extension as ::tanoshi_lib::extensions::Extension>::get_preferences(
+                    &*ext.borrow(),
+                );
+                $crate::shim::write_result(res);
+            });
+        }
+
+        #[no_mangle]
+        pub extern "C" fn set_preferences() {
+            EXTENSION.with(|ext| {
+                let res = $crate::shim::read_object::<Vec<::tanoshi_lib::models::Input>>()
+                    .and_then(|preferences| {
+                        <$extension as ::tanoshi_lib::extensions::Extension>::set_preferences(
+                            &mut *ext.borrow_mut(),
+                            preferences,
+                        )
+                        .map_err(|e| e.to_string().into())
+                    });
+                $crate::shim::write_result(res);
+            });
+        }
+
+        #[no_mangle]
+        pub extern "C" fn get_popular_manga() {
+            EXTENSION.with(|ext| {
+                let res = $crate::shim::read_object::<i64>().and_then(|page| {
+                    <$extension as ::tanoshi_lib::extensions::Extension>::get_popular_manga(
+                        &*ext.borrow(),
+                        page,
+                    )
+                    .map_err(|e| e.to_string().into())
+                });
+                $crate::shim::write_result(res);
+            });
+        }
+
+        #[no_mangle]
+        pub extern "C" fn get_latest_manga() {
+            EXTENSION.with(|ext| {
+                let res = $crate::shim::read_object::<i64>().and_then(|page| {
+                    <$extension as ::tanoshi_lib::extensions::Extension>::get_latest_manga(
+                        &*ext.borrow(),
+                        page,
+                    )
+                    .map_err(|e| e.to_string().into())
+                });
+                $crate::shim::write_result(res);
+            });
+        }
+
+        #[no_mangle]
+        pub extern "C" fn search_manga() {
+            EXTENSION.with(|ext| {
+                let res = $crate::shim::read_object::<(
+                    i64,
+                    Option<String>,
+                    Option<Vec<::tanoshi_lib::models::Input>>,
+                )>()
+                .and_then(|(page, query, filters)| {
+                    <$extension as ::tanoshi_lib::extensions::Extension>::search_manga(
+                        &*ext.borrow(),
+                        page,
+                        query,
+                        filters,
+                    )
+                    .map_err(|e| e.to_string().into())
+                });
+                $crate::shim::write_result(res);
+            });
+        }
+
+        #[no_mangle]
+        pub extern "C" fn get_manga_detail() {
+            EXTENSION.with(|ext| {
+                let res = $crate::shim::read_object::<String>().and_then(|path| {
+                    <$extension as ::tanoshi_lib::extensions::Extension>::get_manga_detail(
+                        &*ext.borrow(),
+                        path,
+                    )
+                    .map_err(|e| e.to_string().into())
+                });
+                $crate::shim::write_result(res);
+            });
+        }
+
+        #[no_mangle]
+        pub extern "C" fn get_chapters() {
+            EXTENSION.with(|ext| {
+                let res = $crate::shim::read_object::<String>().and_then(|path| {
+                    <$extension as ::tanoshi_lib::extensions::Extension>::get_chapters(
+                        &*ext.borrow(),
+                        path,
+                    )
+                    .map_err(|e| e.to_string().into())
+                });
+                $crate::shim::write_result(res);
+            });
+        }
+
+        #[no_mangle]
+        pub extern "C" fn get_pages() {
+            EXTENSION.with(|ext| {
+                let res = $crate::shim::read_object::<String>().and_then(|path| {
+                    <$extension as ::tanoshi_lib::extensions::Extension>::get_pages(
+                        &*ext.borrow(),
+                        path,
+                    )
+                    .map_err(|e| e.to_string().into())
+                });
+                $crate::shim::write_result(res);
+            });
+        }
+    };
+}
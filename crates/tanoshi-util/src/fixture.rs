@@ -0,0 +1,201 @@
+//! Record and replay HTTP fixtures, so extensions can be tested without network access.
+//!
+//! Mode is read from `TANOSHI_HTTP_MODE` (`live`, `record` or `replay`), falling back to
+//! `record` or `replay` when built with the feature of the same name. Fixtures are stored in
+//! `TANOSHI_HTTP_FIXTURE`, or `fixtures/http.ron` if not set.
+//!
+//! Only available with the `fixture` feature, which `__test`, `record` and `replay` enable.
+
+use std::{path::PathBuf, sync::Mutex};
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::http::{Request, Response};
+
+pub const MODE_ENV: &str = "TANOSHI_HTTP_MODE";
+pub const FIXTURE_ENV: &str = "TANOSHI_HTTP_FIXTURE";
+const DEFAULT_FIXTURE: &str = "fixtures/http.ron";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Send requests to the network
+    Live,
+    /// Send requests to the network and save each request and response
+    Record,
+    /// Serve responses from saved fixtures only
+    Replay,
+}
+
+impl Mode {
+    pub fn from_env() -> Self {
+        match std::env::var(MODE_ENV).as_deref() {
+            Ok("live") => Mode::Live,
+            Ok("record") => Mode::Record,
+            Ok("replay") => Mode::Replay,
+            _ => Self::from_features(),
+        }
+    }
+
+    fn from_features() -> Self {
+        if cfg!(feature = "replay") {
+            Mode::Replay
+        } else if cfg!(feature = "record") {
+            Mode::Record
+        } else {
+            Mode::Live
+        }
+    }
+}
+
+/// A recorded request and its response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fixture {
+    pub request: Request,
+    pub response: Response,
+}
+
+impl Fixture {
+    // headers are ignored, they usually contain cookies or tokens that change between runs
+    fn matches(&self, req: &Request) -> bool {
+        self.request.method.eq_ignore_ascii_case(&req.method)
+            && self.request.url == req.url
+            && self.request.body == req.body
+    }
+}
+
+// recording is a read-modify-write of the fixture file
+static FIXTURE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+pub fn fixture_path() -> PathBuf {
+    std::env::var(FIXTURE_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(DEFAULT_FIXTURE))
+}
+
+fn read_fixtures() -> Result<Vec<Fixture>, Box<dyn std::error::Error>> {
+    match std::fs::read_to_string(fixture_path()) {
+        Ok(contents) => Ok(ron::from_str(&contents)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn write_fixtures(fixtures: &[Fixture]) -> Result<(), Box<dyn std::error::Error>> {
+    let path = fixture_path();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let contents = ron::ser::to_string_pretty(&fixtures, ron::ser::PrettyConfig::default())?;
+    std::fs::write(path, contents)?;
+
+    Ok(())
+}
+
+fn error_response(message: String) -> Response {
+    Response {
+        headers: Default::default(),
+        body: message,
+        status: 9999,
+    }
+}
+
+/// Save `req` and `res`, replacing earlier fixture of the same request
+pub fn record(req: &Request, res: &Response) {
+    let _guard = FIXTURE_LOCK.lock();
+
+    let res = read_fixtures().and_then(|mut fixtures| {
+        let fixture = Fixture {
+            request: req.clone(),
+            response: res.clone(),
+        };
+        match fixtures.iter_mut().find(|fixture| fixture.matches(req)) {
+            Some(existing) => *existing = fixture,
+            None => fixtures.push(fixture),
+        }
+        write_fixtures(&fixtures)
+    });
+
+    if let Err(e) = res {
+        log::error!(
+            "failed to record fixture for {} {}: {}",
+            req.method,
+            req.url,
+            e
+        );
+    }
+}
+
+/// Find response of `req` in fixtures, a missing fixture is returned as an error response
+pub fn replay(req: &Request) -> Response {
+    let _guard = FIXTURE_LOCK.lock();
+
+    match read_fixtures() {
+        Ok(fixtures) => fixtures
+            .into_iter()
+            .find(|fixture| fixture.matches(req))
+            .map(|fixture| fixture.response)
+            .unwrap_or_else(|| {
+                error_response(format!(
+                    "no fixture for {} {} in {}",
+                    req.method,
+                    req.url,
+                    fixture_path().display()
+                ))
+            }),
+        Err(e) => error_response(format!(
+            "failed to read fixtures from {}: {}",
+            fixture_path().display(),
+            e
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record_replay() {
+        let path = std::env::temp_dir().join(format!("tanoshi-fixture-{}.ron", std::process::id()));
+        std::env::set_var(FIXTURE_ENV, &path);
+
+        let req = Request::get("https://example.com/manga?page=1");
+        let mut headers = crate::http::Headers::new();
+        headers.insert("content-type".to_string(), vec!["text/html".to_string()]);
+        record(
+            &req,
+            &Response {
+                headers: headers.clone(),
+                body: "first".to_string(),
+                status: 200,
+            },
+        );
+        record(
+            &req,
+            &Response {
+                headers,
+                body: "second".to_string(),
+                status: 200,
+            },
+        );
+        assert_eq!(read_fixtures().unwrap().len(), 1);
+
+        // headers of the request are not matched
+        let mut cookie = crate::http::Headers::new();
+        cookie.insert("cookie".to_string(), vec!["session".to_string()]);
+        let res = replay(&Request {
+            headers: Some(cookie),
+            ..req
+        });
+        assert_eq!(res.status, 200);
+        assert_eq!(res.body, "second");
+        assert_eq!(res.headers["content-type"], vec!["text/html".to_string()]);
+
+        let res = replay(&Request::get("https://example.com/manga?page=2"));
+        assert_eq!(res.status, 9999);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
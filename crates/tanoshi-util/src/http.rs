@@ -89,11 +89,46 @@ extern "C" {
 
 #[cfg(any(feature = "__test", feature = "host"))]
 pub fn http_request(req: Request) -> Response {
+    request(req, None)
+}
+
+/// Same as `http_request`, failing with status 9999 when no response is read within `timeout`
+#[cfg(any(feature = "__test", feature = "host"))]
+pub fn http_request_with_timeout(req: Request, timeout: std::time::Duration) -> Response {
+    request(req, Some(timeout))
+}
+
+#[cfg(all(not(feature = "fixture"), any(feature = "__test", feature = "host")))]
+fn request(req: Request, timeout: Option<std::time::Duration>) -> Response {
+    send(req, timeout)
+}
+
+/// Send `req`, or serve it from fixtures depending on `fixture::Mode::from_env`
+#[cfg(all(feature = "fixture", any(feature = "__test", feature = "host")))]
+fn request(req: Request, timeout: Option<std::time::Duration>) -> Response {
+    use crate::fixture::{self, Mode};
+
+    match Mode::from_env() {
+        Mode::Live => send(req, timeout),
+        Mode::Record => {
+            let res = send(req.clone(), timeout);
+            fixture::record(&req, &res);
+            res
+        }
+        Mode::Replay => fixture::replay(&req),
+    }
+}
+
+#[cfg(any(feature = "__test", feature = "host"))]
+fn send(req: Request, timeout: Option<std::time::Duration>) -> Response {
     use log::debug;
 
     let agent = ureq::builder().user_agent("Tanoshi/0.1.0").build();
 
     let mut request = agent.request(&req.method, &req.url);
+    if let Some(timeout) = timeout {
+        request = request.timeout(timeout);
+    }
     if let Some(headers) = req.headers.as_ref() {
         for (key, values) in headers {
             for value in values {
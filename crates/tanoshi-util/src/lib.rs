@@ -1,3 +1,6 @@
+pub mod export;
+#[cfg(all(feature = "fixture", any(feature = "__test", feature = "host")))]
+pub mod fixture;
 pub mod http;
 pub mod log;
 pub mod shim;
@@ -68,11 +68,17 @@ pub fn print_trace(message: String) {
 }
 
 #[cfg(all(not(feature = "__test"), not(feature = "host")))]
-pub fn warn(message: String) {
+pub fn print_warn(message: String) {
     crate::shim::write_err(message);
     unsafe { host_warn() };
 }
 
+#[cfg(all(not(feature = "__test"), not(feature = "host")))]
+#[deprecated(note = "use `print_warn`, `warn!` calls it")]
+pub fn warn(message: String) {
+    print_warn(message);
+}
+
 #[cfg(all(not(feature = "__test"), not(feature = "host")))]
 #[link(wasm_import_module = "tanoshi")]
 extern "C" {
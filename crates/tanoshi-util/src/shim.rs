@@ -18,3 +18,9 @@ pub fn read_object<T: DeserializeOwned>() -> Result<T, Box<dyn std::error::Error
     io::stdin().read_line(&mut serialized)?;
     Ok(ron::from_str(&serialized)?)
 }
+
+pub fn write_result<T: Serialize, E: std::fmt::Display>(res: Result<T, E>) {
+    if let Err(err) = write_object(res.map_err(|e| e.to_string())) {
+        write_err(format!("{}", err));
+    }
+}
@@ -0,0 +1,126 @@
+use std::{collections::HashMap, ffi::c_void};
+
+use anyhow::{anyhow, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use tanoshi_lib::{
+    ffi::{ExtensionVTable, FfiStr, PluginDeclarationFfi},
+    prelude::{ChapterInfo, Extension, Input, MangaInfo, SourceInfo},
+};
+
+/// An extension loaded through `tanoshi_lib::ffi::PluginDeclarationFfi`
+pub struct FfiExtension {
+    extension: *mut c_void,
+    vtable: ExtensionVTable,
+    source_info: SourceInfo,
+}
+
+// the pointer is a `Box<dyn Extension>` owned by the plugin, which is `Send + Sync`
+unsafe impl Send for FfiExtension {}
+unsafe impl Sync for FfiExtension {}
+
+impl FfiExtension {
+    /// # Safety
+    ///
+    /// `decl` must come from a library that stays loaded for the lifetime of the extension
+    pub unsafe fn new(decl: &PluginDeclarationFfi) -> Result<Self> {
+        let extension = (decl.create)();
+        if extension.is_null() {
+            return Err(anyhow!("extension failed to register"));
+        }
+
+        let source_info =
+            match call::<_, SourceInfo>(extension, &decl.vtable, "get_source_info", ()) {
+                Ok(info) => info,
+                Err(e) => {
+                    (decl.vtable.drop)(extension);
+                    return Err(e);
+                }
+            };
+
+        Ok(Self {
+            extension,
+            vtable: decl.vtable,
+            source_info,
+        })
+    }
+
+    fn call<I: Serialize, O: DeserializeOwned>(&self, method: &str, args: I) -> Result<O> {
+        unsafe { call(self.extension, &self.vtable, method, args) }
+    }
+}
+
+unsafe fn call<I: Serialize, O: DeserializeOwned>(
+    extension: *mut c_void,
+    vtable: &ExtensionVTable,
+    method: &str,
+    args: I,
+) -> Result<O> {
+    let args = serde_json::to_string(&args)?;
+    let buffer = (vtable.call)(extension, FfiStr::new(method), FfiStr::new(&args));
+    let res = serde_json::from_slice::<Result<O, String>>(buffer.as_slice());
+    (vtable.free_buffer)(buffer);
+    res?.map_err(|e| anyhow!(e))
+}
+
+impl Drop for FfiExtension {
+    fn drop(&mut self) {
+        unsafe { (self.vtable.drop)(self.extension) }
+    }
+}
+
+impl Extension for FfiExtension {
+    fn get_source_info(&self) -> SourceInfo {
+        self.source_info.clone()
+    }
+
+    fn headers(&self) -> HashMap<String, String> {
+        self.call("headers", ()).unwrap_or_else(|e| {
+            error!("failed to get headers: {e}");
+            HashMap::new()
+        })
+    }
+
+    fn filter_list(&self) -> Vec<Input> {
+        self.call("filter_list", ()).unwrap_or_else(|e| {
+            error!("failed to get filter list: {e}");
+            vec![]
+        })
+    }
+
+    fn get_preferences(&self) -> Result<Vec<Input>> {
+        self.call("get_preferences", ())
+    }
+
+    fn set_preferences(&mut self, preferences: Vec<Input>) -> Result<()> {
+        self.call("set_preferences", preferences)
+    }
+
+    fn get_popular_manga(&self, page: i64) -> Result<Vec<MangaInfo>> {
+        self.call("get_popular_manga", page)
+    }
+
+    fn get_latest_manga(&self, page: i64) -> Result<Vec<MangaInfo>> {
+        self.call("get_latest_manga", page)
+    }
+
+    fn search_manga(
+        &self,
+        page: i64,
+        query: Option<String>,
+        filters: Option<Vec<Input>>,
+    ) -> Result<Vec<MangaInfo>> {
+        self.call("search_manga", (page, query, filters))
+    }
+
+    fn get_manga_detail(&self, path: String) -> Result<MangaInfo> {
+        self.call("get_manga_detail", path)
+    }
+
+    fn get_chapters(&self, path: String) -> Result<Vec<ChapterInfo>> {
+        self.call("get_chapters", path)
+    }
+
+    fn get_pages(&self, path: String) -> Result<Vec<String>> {
+        self.call("get_pages", path)
+    }
+}
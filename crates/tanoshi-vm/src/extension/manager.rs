@@ -1,36 +1,121 @@
 use std::{
+    collections::HashMap,
     path::{Path, PathBuf},
-    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    str::FromStr,
+    sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::Instant,
 };
 
 use anyhow::{anyhow, bail, Result};
+use ed25519_dalek::PublicKey;
 use fnv::FnvHashMap;
 use libloading::Library;
-use tanoshi_lib::prelude::{Input, PluginDeclaration, SourceInfo};
+use serde::de::DeserializeOwned;
+use tanoshi_lib::{
+    ffi::{PluginDeclarationFfi, ABI_VERSION, DECLARATION_SYMBOL},
+    prelude::{Extension, Input, PluginDeclaration, SourceInfo, Version},
+};
+use wasmtime::Engine;
+
+use crate::{
+    extension::timeout::with_deadline,
+    prelude::{
+        decode_public_key, fetch_file, fetch_index, is_native_compatible, wasm_engine,
+        FfiExtension, Operation, ProcessExtension, Repository, Source, SourceIndex, TimeoutError,
+        TimeoutTracker, Timeouts, WasmExtension, WorkerOptions,
+    },
+    PLUGIN_EXTENSION, WASM_EXTENSION, WASM_TARGET,
+};
+
+/// Check `lib_version` an extension is built with against `tanoshi_lib::LIB_VERSION`. Only a
+/// `legacy` plugin shares memory layout with the host, others exchange serialized data
+fn check_lib_version(lib_version: &str, legacy: bool) -> Result<()> {
+    let required = Version::from_str(lib_version)?;
+    let host = Version::from_str(tanoshi_lib::LIB_VERSION)?;
+    let compatible = if legacy {
+        host.is_compatible_with(&required)
+    } else {
+        host.is_serde_compatible_with(&required)
+    };
+    if !compatible {
+        bail!(
+            "Version mismatch: extension.lib_version={} is not compatible with tanoshi_lib::lib_version={}",
+            lib_version,
+            tanoshi_lib::LIB_VERSION
+        );
+    }
 
-use crate::{prelude::Source, PLUGIN_EXTENSION};
+    Ok(())
+}
+
+/// File name of `version` in version history. Versions come from `index.json` and extensions,
+/// so only `major.minor.patch` is accepted to keep them from escaping the extension directory
+fn version_file_name(version: &str, extension: &str) -> Result<String> {
+    let version = Version::from_str(version).map_err(|_| anyhow!("invalid version {version:?}"))?;
+    Ok(format!("{version}.{extension}"))
+}
 
 #[derive(Clone)]
 pub struct ExtensionManager {
     dir: PathBuf,
-    extensions: Arc<RwLock<FnvHashMap<i64, Source>>>,
+    engine: Engine,
+    worker: Option<WorkerOptions>,
+    public_key: Option<PublicKey>,
+    versions_to_keep: usize,
+    timeouts: Arc<Timeouts>,
+    tracker: Arc<Mutex<TimeoutTracker>>,
+    // serializes read-modify-write of `.repositories.json`
+    repositories_lock: Arc<tokio::sync::Mutex<()>>,
+    // a source is shared with its in-flight calls, see `call`
+    extensions: Arc<RwLock<FnvHashMap<i64, Arc<Source>>>>,
 }
 
 impl ExtensionManager {
     pub fn new<P: AsRef<Path>>(extension_dir: P) -> Self {
         Self {
             dir: PathBuf::new().join(extension_dir),
+            engine: wasm_engine(),
+            worker: None,
+            public_key: None,
+            versions_to_keep: 3,
+            timeouts: Arc::new(Timeouts::default()),
+            tracker: Arc::new(Mutex::new(TimeoutTracker::default())),
+            repositories_lock: Arc::new(tokio::sync::Mutex::new(())),
             extensions: Arc::new(RwLock::new(FnvHashMap::default())),
         }
     }
 
-    fn read(&self) -> Result<RwLockReadGuard<FnvHashMap<i64, Source>>> {
+    /// Only install extensions signed by `public_key`, unless repository has its own key
+    pub fn with_public_key(mut self, public_key: PublicKey) -> Self {
+        self.public_key = Some(public_key);
+        self
+    }
+
+    /// Number of versions kept per source for rollback, including the running one
+    pub fn with_versions_to_keep(mut self, versions_to_keep: usize) -> Self {
+        self.versions_to_keep = versions_to_keep.max(1);
+        self
+    }
+
+    /// Set deadlines of extension calls
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = Arc::new(timeouts);
+        self
+    }
+
+    /// Run native extensions in child processes, see `ProcessExtension`
+    pub fn with_worker(mut self, options: WorkerOptions) -> Self {
+        self.worker = Some(options);
+        self
+    }
+
+    fn read(&self) -> Result<RwLockReadGuard<FnvHashMap<i64, Arc<Source>>>> {
         self.extensions
             .read()
             .map_err(|e| anyhow!("failed to lock read: {e}"))
     }
 
-    fn write(&self) -> Result<RwLockWriteGuard<FnvHashMap<i64, Source>>> {
+    fn write(&self) -> Result<RwLockWriteGuard<FnvHashMap<i64, Arc<Source>>>> {
         self.extensions
             .write()
             .map_err(|e| anyhow!("failed to lock write: {e}"))
@@ -42,7 +127,7 @@ impl ExtensionManager {
             let mut name = format!("{:?}", entry.file_name());
             name.remove(0);
             name.remove(name.len() - 1);
-            if name.ends_with(PLUGIN_EXTENSION) {
+            if name.ends_with(PLUGIN_EXTENSION) || name.ends_with(WASM_EXTENSION) {
                 if let Err(e) = self.load(&name).await {
                     error!("failed to load {name}: {e}");
                 }
@@ -63,36 +148,312 @@ impl ExtensionManager {
             .collect())
     }
 
-    pub async fn install(&self, repo_url: &str, name: &str) -> Result<()> {
-        let source_file_url = format!(
-            "{}/{}/{}.{}",
-            repo_url,
-            env!("TARGET"),
-            name.to_lowercase(),
-            PLUGIN_EXTENSION
-        );
+    /// Install extension `name` from `repo`, the repository is recorded as its origin
+    pub async fn install(&self, repo: &Repository, name: &str) -> Result<()> {
+        let repo_url = repo.url.as_str();
+        let public_key = match &repo.public_key {
+            Some(public_key) => Some(decode_public_key(public_key)?),
+            None => self.public_key,
+        };
+
+        let name = name.to_lowercase();
+        let index = fetch_index::<SourceIndex>(repo_url)
+            .await?
+            .into_iter()
+            .find(|index| index.name.to_lowercase() == name)
+            .ok_or_else(|| anyhow!("{name} not found in {}", repo.name))?;
+        if name.is_empty() || name.contains("..") || name.contains(|c| c == '/' || c == '\\') {
+            bail!("invalid extension name {name:?}");
+        }
+
+        // native build that can't be loaded is skipped in favor of sandboxed build
+        let native = if is_native_compatible(&index.rustc_version, index.abi_version) {
+            let native_file = format!("{name}.{PLUGIN_EXTENSION}");
+            fetch_file(repo_url, env!("TARGET"), &native_file).await?
+        } else {
+            None
+        };
+        let (contents, extension, target) = match native {
+            Some(contents) => (contents, PLUGIN_EXTENSION, env!("TARGET")),
+            // no native build for this target, fallback to sandboxed build
+            None => {
+                let wasm_file = format!("{name}.{WASM_EXTENSION}");
+                let contents = fetch_file(repo_url, WASM_TARGET, &wasm_file)
+                    .await?
+                    .ok_or_else(|| anyhow!("{name} has no build for {}", env!("TARGET")))?;
+                (contents, WASM_EXTENSION, WASM_TARGET)
+            }
+        };
+
+        // verify before anything touches the disk
+        let file_name = version_file_name(&index.version, extension)?;
+        match index.files.get(target) {
+            Some(file) => file
+                .verify(
+                    &name,
+                    &index.version,
+                    target,
+                    &contents,
+                    public_key.as_ref(),
+                )
+                .map_err(|e| anyhow!("failed to verify {name}: {e}"))?,
+            None if public_key.is_some() => {
+                bail!("failed to verify {name}: no checksum for {target}")
+            }
+            None => warn!("{name} has no checksum for {target}, skip verification"),
+        }
 
-        info!("downloading {}", source_file_url);
+        // keep currently installed version around for rollback
+        if let Ok(installed) = self.get_source_info(index.id) {
+            self.archive(&name, &installed.version).await?;
+        }
 
-        let contents = reqwest::get(&source_file_url).await?.bytes().await?;
+        let versions_dir = self.versions_dir(&name);
+        tokio::fs::create_dir_all(&versions_dir).await?;
+        let path = versions_dir.join(file_name);
+        tokio::fs::write(&path, contents).await?;
 
-        tokio::fs::write(
-            self.dir
-                .join(&name.to_lowercase())
-                .with_extension(PLUGIN_EXTENSION),
-            contents,
-        )
-        .await?;
+        self.swap(&name, &path).await?;
+        self.set_repository(index.id, Some(&repo.name)).await?;
+        self.prune_versions(&name).await
+    }
+
+    fn versions_dir(&self, name: &str) -> PathBuf {
+        self.dir.join("versions").join(name)
+    }
+
+    /// Copy active file of `name` to its version history if it isn't there yet. A version that
+    /// is not `major.minor.patch` can't be archived, it is skipped with a warning
+    async fn archive(&self, name: &str, version: &str) -> Result<()> {
+        if let Err(e) = version_file_name(version, PLUGIN_EXTENSION) {
+            warn!("skip archiving {name} {version}: {e}");
+            return Ok(());
+        }
+
+        for extension in [PLUGIN_EXTENSION, WASM_EXTENSION] {
+            let active = self.dir.join(name).with_extension(extension);
+            let archived = self
+                .versions_dir(name)
+                .join(version_file_name(version, extension)?);
+            if active.exists() && !archived.exists() {
+                tokio::fs::create_dir_all(self.versions_dir(name)).await?;
+                tokio::fs::copy(&active, &archived).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Load `path` from version history and replace the running source with it.
+    ///
+    /// Each version is loaded from its own file, the library of the previous version
+    /// stays loaded until its in-flight calls finish.
+    async fn swap(&self, name: &str, path: &Path) -> Result<()> {
+        let mut source = self.load_file(path)?;
+        self.apply_preferences(&mut source).await?;
+
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .ok_or_else(|| anyhow!("invalid extension file {}", path.display()))?;
+
+        // active file is loaded on next start, replace it by rename so a mapped library
+        // is never overwritten
+        let tmp = self.dir.join(format!(".{name}.{extension}.tmp"));
+        tokio::fs::copy(path, &tmp).await?;
+        tokio::fs::rename(&tmp, self.dir.join(name).with_extension(extension)).await?;
+        for other in [PLUGIN_EXTENSION, WASM_EXTENSION] {
+            let other = self.dir.join(name).with_extension(other);
+            if other.extension().and_then(|ext| ext.to_str()) != Some(extension) && other.exists() {
+                tokio::fs::remove_file(other).await?;
+            }
+        }
 
-        let source = self.load_library(&name.to_lowercase())?;
         self.insert(source).await
     }
 
+    /// Remove all but `versions_to_keep` newest versions of `name`
+    async fn prune_versions(&self, name: &str) -> Result<()> {
+        let versions = self.list_versions(name).await?;
+        for (_, path) in versions.into_iter().skip(self.versions_to_keep) {
+            info!("remove old version {}", path.display());
+            tokio::fs::remove_file(path).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Versions of `name` in version history, newest first
+    async fn list_versions(&self, name: &str) -> Result<Vec<(String, PathBuf)>> {
+        let mut versions = vec![];
+
+        let mut read_dir = match tokio::fs::read_dir(self.versions_dir(name)).await {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(versions),
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(entry) = read_dir.next_entry().await? {
+            let path = entry.path();
+            let version = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| {
+                    name.strip_suffix(&format!(".{PLUGIN_EXTENSION}"))
+                        .or_else(|| name.strip_suffix(&format!(".{WASM_EXTENSION}")))
+                })
+                .map(|version| version.to_string());
+            if let Some(version) = version {
+                versions.push((version, path));
+            }
+        }
+
+        versions.sort_by(|(a, _), (b, _)| {
+            Version::from_str(b)
+                .ok()
+                .cmp(&Version::from_str(a).ok())
+                .then_with(|| b.cmp(a))
+        });
+
+        Ok(versions)
+    }
+
+    /// Versions of a source available to roll back to, newest first
+    pub async fn get_versions(&self, source_id: i64) -> Result<Vec<String>> {
+        let name = self.get_source_info(source_id)?.name.to_lowercase();
+        Ok(self
+            .list_versions(&name)
+            .await?
+            .into_iter()
+            .map(|(version, _)| version)
+            .collect())
+    }
+
+    /// Replace running source with `version` from its version history
+    pub async fn rollback(&self, source_id: i64, version: &str) -> Result<()> {
+        let installed = self.get_source_info(source_id)?;
+        if installed.version == version {
+            bail!("{} {version} is already running", installed.name);
+        }
+
+        let name = installed.name.to_lowercase();
+        let (_, path) = self
+            .list_versions(&name)
+            .await?
+            .into_iter()
+            .find(|(v, _)| v == version)
+            .ok_or_else(|| anyhow!("{} has no version {version}", installed.name))?;
+
+        self.archive(&name, &installed.version).await?;
+        self.swap(&name, &path).await
+    }
+
+    fn repositories_path(&self) -> PathBuf {
+        self.dir.join(".repositories.json")
+    }
+
+    async fn read_repositories(&self) -> HashMap<i64, String> {
+        tokio::fs::read_to_string(self.repositories_path())
+            .await
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    async fn set_repository(&self, source_id: i64, repository: Option<&str>) -> Result<()> {
+        let _guard = self.repositories_lock.lock().await;
+        let mut repositories = self.read_repositories().await;
+        match repository {
+            Some(repository) => repositories.insert(source_id, repository.to_string()),
+            None => repositories.remove(&source_id),
+        };
+        // readers don't take the lock, so replace the file instead of truncating it
+        let tmp_path = self.repositories_path().with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, serde_json::to_string_pretty(&repositories)?).await?;
+        tokio::fs::rename(&tmp_path, self.repositories_path()).await?;
+
+        Ok(())
+    }
+
+    /// Name of the repository a source is installed from
+    pub async fn get_repository(&self, source_id: i64) -> Result<Option<String>> {
+        Ok(self.read_repositories().await.remove(&source_id))
+    }
+
+    /// Fetch and merge `index.json` of `repositories`, which are in priority order.
+    ///
+    /// When a source is listed by more than one repository, the entry from the repository
+    /// it is installed from is used, otherwise the one with highest priority.
+    /// Repositories that fail to respond are skipped.
+    pub async fn fetch_indexes<T, F>(
+        &self,
+        repositories: &[Repository],
+        id: F,
+    ) -> Vec<(Repository, T)>
+    where
+        T: DeserializeOwned,
+        F: Fn(&T) -> i64,
+    {
+        let installed_from = self.read_repositories().await;
+
+        let mut merged: Vec<(Repository, T)> = vec![];
+        let mut positions: HashMap<i64, usize> = HashMap::new();
+        for repo in repositories {
+            let indexes = match fetch_index::<T>(&repo.url).await {
+                Ok(indexes) => indexes,
+                Err(e) => {
+                    error!("failed to fetch index from {}: {e}", repo.name);
+                    continue;
+                }
+            };
+
+            for index in indexes {
+                let source_id = id(&index);
+                match positions.get(&source_id) {
+                    None => {
+                        positions.insert(source_id, merged.len());
+                        merged.push((repo.clone(), index));
+                    }
+                    Some(&pos) if installed_from.get(&source_id) == Some(&repo.name) => {
+                        merged[pos] = (repo.clone(), index);
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+
+        merged
+    }
+
+    fn load_wasm(&self, library_path: &Path) -> Result<Source> {
+        info!("load {:?}", library_path.display());
+
+        let extension = WasmExtension::load(&self.engine, library_path)?;
+        check_lib_version(&extension.lib_version, false)?;
+
+        Ok(Source::from_wasm(extension))
+    }
+
     fn load_library(&self, name: &str) -> Result<Source> {
+        let wasm_path = self.dir.join(name).with_extension(WASM_EXTENSION);
+        if name.ends_with(WASM_EXTENSION)
+            || (!name.ends_with(PLUGIN_EXTENSION) && wasm_path.exists())
+        {
+            return self.load_wasm(&wasm_path);
+        }
+
         let library_path = PathBuf::new()
             .join(&self.dir)
             .join(name)
             .with_extension(PLUGIN_EXTENSION);
+        self.load_file(&library_path)
+    }
+
+    /// Load extension from `library_path`, either native library or wasm module
+    pub(crate) fn load_file(&self, library_path: &Path) -> Result<Source> {
+        if library_path.extension().and_then(|ext| ext.to_str()) == Some(WASM_EXTENSION) {
+            return self.load_wasm(library_path);
+        }
+
         info!("load {:?}", library_path.display());
 
         #[cfg(target_os = "macos")]
@@ -106,8 +467,40 @@ impl ExtensionManager {
             error!("failed to run install_name_tool: {}", e);
         }
 
+        if let Some(options) = &self.worker {
+            let extension = ProcessExtension::spawn(options.clone(), library_path)?;
+            return Ok(Source::from_process(extension));
+        }
+
         unsafe {
-            let library = Library::new(&library_path)?;
+            let library = Library::new(library_path)?;
+
+            // prefer stable interface, it doesn't require matching `rustc` version
+            let decl = library
+                .get::<unsafe extern "C" fn() -> PluginDeclarationFfi>(DECLARATION_SYMBOL)
+                .map(|declare| declare())
+                .ok();
+            if let Some(decl) = decl {
+                if decl.abi_version != ABI_VERSION {
+                    bail!(
+                        "ABI mismatch: extension.abi_version={} != tanoshi_lib::ffi::abi_version={}",
+                        decl.abi_version,
+                        ABI_VERSION
+                    );
+                }
+
+                let rustc_version = decl.rustc_version.as_str();
+                let core_version = decl.core_version.as_str();
+                check_lib_version(core_version, false)?;
+
+                let extension = FfiExtension::new(&decl)?;
+                return Ok(Source::from_ffi(
+                    library,
+                    rustc_version,
+                    core_version,
+                    extension,
+                ));
+            }
 
             let decl = library
                 .get::<*mut PluginDeclaration>(b"plugin_declaration\0")?
@@ -121,13 +514,7 @@ impl ExtensionManager {
                 );
             }
 
-            if decl.core_version != tanoshi_lib::LIB_VERSION {
-                bail!(
-                    "Version mismatch: extension.lib_version={} != tanoshi_lib::lib_version={}",
-                    decl.core_version,
-                    tanoshi_lib::LIB_VERSION
-                );
-            }
+            check_lib_version(decl.core_version, true)?;
 
             let mut registrar = Source::new(library, decl.rustc_version, decl.core_version);
             (decl.register)(&mut registrar);
@@ -138,6 +525,11 @@ impl ExtensionManager {
 
     pub async fn load(&self, name: &str) -> Result<()> {
         let mut source = self.load_library(name)?;
+        self.apply_preferences(&mut source).await?;
+        self.insert(source).await
+    }
+
+    async fn apply_preferences(&self, source: &mut Source) -> Result<()> {
         let source_name = source
             .extension
             .get()
@@ -159,7 +551,8 @@ impl ExtensionManager {
                 .ok_or_else(|| anyhow!("not initiated"))?
                 .set_preferences(preferences)?;
         }
-        self.insert(source).await
+
+        Ok(())
     }
 
     pub async fn insert(&self, source: Source) -> Result<()> {
@@ -168,21 +561,28 @@ impl ExtensionManager {
             .get()
             .map(|s| s.get_source_info())
             .ok_or_else(|| anyhow!("error"))?;
-        self.write()?.insert(info.id, source);
+        self.write()?.insert(info.id, Arc::new(source));
         Ok(())
     }
 
+    /// Unload a source, its files are moved to version history so it can be reinstalled
     pub async fn unload(&self, source_id: i64) -> Result<()> {
-        if let Some(source) = self
+        let source = self
             .write()?
             .remove(&source_id)
-            .and_then(|s| s.extension.get().map(|s| s.get_source_info()))
-        {
-            std::fs::remove_file(
-                self.dir
-                    .join(&source.name.to_lowercase())
-                    .with_extension(PLUGIN_EXTENSION),
-            )?;
+            .and_then(|s| s.extension.get().map(|s| s.get_source_info()));
+        if let Some(source) = source {
+            let name = source.name.to_lowercase();
+            self.archive(&name, &source.version).await?;
+            self.prune_versions(&name).await?;
+
+            for extension in [PLUGIN_EXTENSION, WASM_EXTENSION] {
+                let path = self.dir.join(&name).with_extension(extension);
+                if path.exists() {
+                    tokio::fs::remove_file(path).await?;
+                }
+            }
+            self.set_repository(source.id, None).await?;
         }
         Ok(())
     }
@@ -199,6 +599,14 @@ impl ExtensionManager {
         Ok((source.rustc_version.clone(), source.lib_version.clone()))
     }
 
+    pub fn get_abi_version(&self, source_id: i64) -> Result<Option<u32>> {
+        Ok(self
+            .read()?
+            .get(&source_id)
+            .ok_or_else(|| anyhow!("no such source"))?
+            .abi_version)
+    }
+
     pub fn get_source_info(&self, source_id: i64) -> Result<SourceInfo> {
         Ok(self
             .read()?
@@ -231,20 +639,38 @@ impl ExtensionManager {
             .get_preferences()
     }
 
+    /// Preferences are set on a newly loaded instance of the source that replaces the running
+    /// one, calls in flight keep using the previous instance
     pub async fn set_preferences(&self, source_id: i64, preferences: Vec<Input>) -> Result<()> {
-        self.write()?
-            .get_mut(&source_id)
-            .ok_or_else(|| anyhow!("no such source"))?
+        let name = self.get_source_info(source_id)?.name.to_lowercase();
+        let installed = [PLUGIN_EXTENSION, WASM_EXTENSION]
+            .iter()
+            .any(|extension| self.dir.join(&name).with_extension(extension).exists());
+
+        if installed {
+            let mut source = self.load_library(&name)?;
+            source
+                .extension
+                .get_mut()
+                .ok_or_else(|| anyhow!("uninitiated"))?
+                .set_preferences(preferences.clone())?;
+            self.insert(source).await?;
+        } else {
+            // a source registered in memory, e.g. local source, can't be loaded again
+            Arc::get_mut(
+                self.write()?
+                    .get_mut(&source_id)
+                    .ok_or_else(|| anyhow!("no such source"))?,
+            )
+            .ok_or_else(|| anyhow!("source is busy, try again later"))?
             .extension
             .get_mut()
             .ok_or_else(|| anyhow!("uninitiated"))?
             .set_preferences(preferences.clone())?;
+        }
 
-        let source_info = self.get_source_info(source_id)?;
         tokio::fs::write(
-            self.dir
-                .join(source_info.name.to_lowercase())
-                .with_extension("json"),
+            self.dir.join(name).with_extension("json"),
             serde_json::to_string_pretty(&preferences)?,
         )
         .await?;
@@ -252,24 +678,82 @@ impl ExtensionManager {
         Ok(())
     }
 
+    /// Run `f` on a blocking thread with the deadline of `operation`.
+    ///
+    /// Wasm and isolated extensions stop the call at the deadline. A native extension
+    /// running in-process can't be cancelled, on timeout caller gets `TimeoutError` and
+    /// the call is left to finish on its own. It holds its own reference to the source,
+    /// so the source can still be unloaded meanwhile.
+    ///
+    /// Only a successful call resets the consecutive timeouts of a source, a call that
+    /// fails at its deadline is stopped by the runtime and counts as a timeout.
+    async fn call<T, F>(&self, source_id: i64, operation: Operation, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&dyn Extension) -> Result<T> + Send + 'static,
+    {
+        let source = self
+            .read()?
+            .get(&source_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("no such source"))?;
+
+        let timeout = self.timeouts.get(source_id, operation);
+        let deadline = Instant::now() + timeout;
+        let res = tokio::time::timeout(
+            timeout,
+            tokio::task::spawn_blocking(move || {
+                with_deadline(deadline, || {
+                    f(source
+                        .extension
+                        .get()
+                        .ok_or_else(|| anyhow!("uninitiated"))?
+                        .as_ref())
+                })
+            }),
+        )
+        .await;
+
+        let mut tracker = self
+            .tracker
+            .lock()
+            .map_err(|e| anyhow!("failed to lock timeout tracker: {e}"))?;
+        match res {
+            Ok(Ok(Ok(value))) => {
+                tracker.record_success(source_id);
+                Ok(value)
+            }
+            Ok(Ok(Err(e))) if Instant::now() < deadline => Err(e),
+            Ok(Err(e)) => Err(e.into()),
+            Ok(Ok(Err(_))) | Err(_) => {
+                tracker.record_timeout(source_id, &self.timeouts);
+                Err(TimeoutError {
+                    source_id,
+                    operation,
+                    timeout,
+                }
+                .into())
+            }
+        }
+    }
+
+    /// Whether a source is temporarily suspended after repeatedly timing out
+    pub fn is_suspended(&self, source_id: i64) -> bool {
+        self.tracker
+            .lock()
+            .map(|tracker| tracker.is_suspended(source_id))
+            .unwrap_or(false)
+    }
+
     pub async fn get_popular_manga(
         &self,
         source_id: i64,
         page: i64,
     ) -> Result<Vec<tanoshi_lib::prelude::MangaInfo>> {
-        let extensions = self.extensions.clone();
-        tokio::task::spawn_blocking(move || {
-            extensions
-                .read()
-                .map_err(|e| anyhow!("failed to lock read: {e}"))?
-                .get(&source_id)
-                .ok_or_else(|| anyhow!("no such source"))?
-                .extension
-                .get()
-                .ok_or_else(|| anyhow!("uninitiated"))?
-                .get_popular_manga(page)
+        self.call(source_id, Operation::GetPopularManga, move |ext| {
+            ext.get_popular_manga(page)
         })
-        .await?
+        .await
     }
 
     pub async fn get_latest_manga(
@@ -277,19 +761,10 @@ impl ExtensionManager {
         source_id: i64,
         page: i64,
     ) -> Result<Vec<tanoshi_lib::prelude::MangaInfo>> {
-        let extensions = self.extensions.clone();
-        tokio::task::spawn_blocking(move || {
-            extensions
-                .read()
-                .map_err(|e| anyhow!("failed to lock read: {e}"))?
-                .get(&source_id)
-                .ok_or_else(|| anyhow!("no such source"))?
-                .extension
-                .get()
-                .ok_or_else(|| anyhow!("uninitiated"))?
-                .get_latest_manga(page)
+        self.call(source_id, Operation::GetLatestManga, move |ext| {
+            ext.get_latest_manga(page)
         })
-        .await?
+        .await
     }
 
     pub async fn search_manga(
@@ -299,19 +774,10 @@ impl ExtensionManager {
         query: Option<String>,
         filters: Option<Vec<Input>>,
     ) -> Result<Vec<tanoshi_lib::prelude::MangaInfo>> {
-        let extensions = self.extensions.clone();
-        tokio::task::spawn_blocking(move || {
-            extensions
-                .read()
-                .map_err(|e| anyhow!("failed to lock read: {e}"))?
-                .get(&source_id)
-                .ok_or_else(|| anyhow!("no such source"))?
-                .extension
-                .get()
-                .ok_or_else(|| anyhow!("uninitiated"))?
-                .search_manga(page, query, filters)
+        self.call(source_id, Operation::SearchManga, move |ext| {
+            ext.search_manga(page, query, filters)
         })
-        .await?
+        .await
     }
 
     pub async fn get_manga_detail(
@@ -319,19 +785,10 @@ impl ExtensionManager {
         source_id: i64,
         path: String,
     ) -> Result<tanoshi_lib::prelude::MangaInfo> {
-        let extensions = self.extensions.clone();
-        tokio::task::spawn_blocking(move || {
-            extensions
-                .read()
-                .map_err(|e| anyhow!("failed to lock read: {e}"))?
-                .get(&source_id)
-                .ok_or_else(|| anyhow!("no such source"))?
-                .extension
-                .get()
-                .ok_or_else(|| anyhow!("uninitiated"))?
-                .get_manga_detail(path)
+        self.call(source_id, Operation::GetMangaDetail, move |ext| {
+            ext.get_manga_detail(path)
         })
-        .await?
+        .await
     }
 
     pub async fn get_chapters(
@@ -339,34 +796,16 @@ impl ExtensionManager {
         source_id: i64,
         path: String,
     ) -> Result<Vec<tanoshi_lib::prelude::ChapterInfo>> {
-        let extensions = self.extensions.clone();
-        tokio::task::spawn_blocking(move || {
-            extensions
-                .read()
-                .map_err(|e| anyhow!("failed to lock read: {e}"))?
-                .get(&source_id)
-                .ok_or_else(|| anyhow!("no such source"))?
-                .extension
-                .get()
-                .ok_or_else(|| anyhow!("uninitiated"))?
-                .get_chapters(path)
+        self.call(source_id, Operation::GetChapters, move |ext| {
+            ext.get_chapters(path)
         })
-        .await?
+        .await
     }
 
     pub async fn get_pages(&self, source_id: i64, path: String) -> Result<Vec<String>> {
-        let extensions = self.extensions.clone();
-        tokio::task::spawn_blocking(move || {
-            extensions
-                .read()
-                .map_err(|e| anyhow!("failed to lock read: {e}"))?
-                .get(&source_id)
-                .ok_or_else(|| anyhow!("no such source"))?
-                .extension
-                .get()
-                .ok_or_else(|| anyhow!("uninitiated"))?
-                .get_pages(path)
+        self.call(source_id, Operation::GetPages, move |ext| {
+            ext.get_pages(path)
         })
-        .await?
+        .await
     }
 }
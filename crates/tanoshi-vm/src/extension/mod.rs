@@ -3,3 +3,18 @@ pub use source::*;
 
 pub mod manager;
 pub use manager::*;
+
+pub mod ffi;
+pub use ffi::*;
+
+pub mod process;
+pub use process::*;
+
+pub mod repository;
+pub use repository::*;
+
+pub mod timeout;
+pub use timeout::*;
+
+pub mod wasm;
+pub use wasm::*;
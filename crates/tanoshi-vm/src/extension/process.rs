@@ -0,0 +1,388 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tanoshi_lib::prelude::{ChapterInfo, Extension, Input, MangaInfo, SourceInfo};
+use tanoshi_util::http::{Request, Response};
+
+use super::{timeout::remaining, ExtensionManager};
+
+/// Options to run each extension in its own child process
+#[derive(Debug, Clone)]
+pub struct WorkerOptions {
+    /// Program to spawn, it is called with `args` followed by the library path
+    /// and expected to call `run_worker` with that path
+    pub program: PathBuf,
+    pub args: Vec<String>,
+}
+
+/// How long a worker may take to respond when a call has no deadline, e.g. while loading.
+/// Otherwise the worker is killed once the deadline set by `ExtensionManager` passes.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// First frame sent by worker after loading the library
+#[derive(Serialize, Deserialize)]
+struct Handshake {
+    rustc_version: String,
+    lib_version: String,
+    abi_version: Option<u32>,
+}
+
+// status of a response with the result of a call in its body, any other status means
+// the body is an error message
+const STATUS_OK: i32 = 200;
+const STATUS_ERROR: i32 = 500;
+
+/// Call of extension method `method` with JSON encoded arguments as body
+fn request(method: &str, args: String) -> Request {
+    Request {
+        method: method.to_string(),
+        url: String::new(),
+        headers: None,
+        body: Some(args),
+    }
+}
+
+fn response(res: Result<String, String>) -> Response {
+    let (status, body) = match res {
+        Ok(body) => (STATUS_OK, body),
+        Err(e) => (STATUS_ERROR, e),
+    };
+
+    Response {
+        headers: HashMap::new(),
+        body,
+        status,
+    }
+}
+
+fn parse_response<O: DeserializeOwned>(frame: &[u8]) -> Result<O> {
+    let res = serde_json::from_slice::<Response>(frame)?;
+    if res.status != STATUS_OK {
+        bail!(res.body);
+    }
+
+    Ok(serde_json::from_str(&res.body)?)
+}
+
+// frame is a little endian u32 length followed by JSON encoded `Request` or `Response`
+fn write_frame<W: Write>(writer: &mut W, data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_le_bytes())?;
+    writer.write_all(data)?;
+    writer.flush()
+}
+
+fn read_frame<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut len = [0_u8; 4];
+    reader.read_exact(&mut len)?;
+    let mut data = vec![0_u8; u32::from_le_bytes(len) as usize];
+    reader.read_exact(&mut data)?;
+    Ok(data)
+}
+
+struct Worker {
+    child: Child,
+    stdin: ChildStdin,
+    frames: Receiver<Vec<u8>>,
+}
+
+impl Worker {
+    fn spawn(options: &WorkerOptions, path: &Path) -> Result<(Self, Handshake)> {
+        let mut child = Command::new(&options.program)
+            .args(&options.args)
+            .arg(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()?;
+
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!("no worker stdin"))?;
+        let mut stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow!("no worker stdout"))?;
+
+        // reading on separate thread so calls can time out
+        let (tx, frames) = mpsc::channel();
+        std::thread::spawn(move || {
+            while let Ok(frame) = read_frame(&mut stdout) {
+                if tx.send(frame).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut worker = Self {
+            child,
+            stdin,
+            frames,
+        };
+        let handshake = parse_response::<Handshake>(&worker.recv()?)?;
+
+        Ok((worker, handshake))
+    }
+
+    fn recv(&mut self) -> Result<Vec<u8>> {
+        let timeout = remaining(DEFAULT_TIMEOUT);
+        match self.frames.recv_timeout(timeout) {
+            Ok(frame) => Ok(frame),
+            Err(RecvTimeoutError::Timeout) => {
+                bail!("extension worker timed out after {}s", timeout.as_secs())
+            }
+            Err(RecvTimeoutError::Disconnected) => match self.child.wait() {
+                Ok(status) => bail!("extension worker exited: {status}"),
+                Err(e) => bail!("extension worker exited: {e}"),
+            },
+        }
+    }
+
+    fn request(&mut self, method: &str, args: String) -> Result<Vec<u8>> {
+        write_frame(
+            &mut self.stdin,
+            &serde_json::to_vec(&request(method, args))?,
+        )?;
+        self.recv()
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// An extension running in a child process, a crash only affects this extension.
+///
+/// Calls are sent one at a time, if the worker crashes or a call times out
+/// the worker is killed and restarted on next call.
+pub struct ProcessExtension {
+    path: PathBuf,
+    options: WorkerOptions,
+    worker: Mutex<Option<Worker>>,
+    // replayed when worker is restarted
+    preferences: Option<Vec<Input>>,
+    source_info: SourceInfo,
+    pub(crate) rustc_version: String,
+    pub(crate) lib_version: String,
+    pub(crate) abi_version: Option<u32>,
+}
+
+impl ProcessExtension {
+    pub fn spawn<P: AsRef<Path>>(options: WorkerOptions, path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let (mut worker, handshake) = Worker::spawn(&options, &path)?;
+
+        let source_info =
+            parse_response::<SourceInfo>(&worker.request("get_source_info", "null".to_string())?)?;
+
+        Ok(Self {
+            path,
+            options,
+            worker: Mutex::new(Some(worker)),
+            preferences: None,
+            source_info,
+            rustc_version: handshake.rustc_version,
+            lib_version: handshake.lib_version,
+            abi_version: handshake.abi_version,
+        })
+    }
+
+    fn respawn(&self) -> Result<Worker> {
+        info!("restart extension worker for {}", self.path.display());
+
+        let (mut worker, _) = Worker::spawn(&self.options, &self.path)?;
+        if let Some(preferences) = &self.preferences {
+            let frame = worker.request("set_preferences", serde_json::to_string(preferences)?)?;
+            parse_response::<()>(&frame)?;
+        }
+
+        Ok(worker)
+    }
+
+    fn call<I: Serialize, O: DeserializeOwned>(&self, method: &str, args: I) -> Result<O> {
+        let args = serde_json::to_string(&args)?;
+
+        let mut worker = self
+            .worker
+            .lock()
+            .map_err(|e| anyhow!("failed to lock worker: {e}"))?;
+        // waiting for the previous call may take all the time, worker is healthy then
+        if remaining(DEFAULT_TIMEOUT).is_zero() {
+            bail!("{method} not started before its deadline");
+        }
+
+        let res = match worker.as_mut() {
+            Some(w) => w.request(method, args),
+            None => self
+                .respawn()
+                .and_then(|w| worker.insert(w).request(method, args)),
+        };
+
+        let frame = match res {
+            Ok(frame) => frame,
+            Err(e) => {
+                // dropping worker kills the process, next call will spawn a new one
+                worker.take();
+                return Err(e);
+            }
+        };
+
+        parse_response(&frame)
+    }
+}
+
+impl Extension for ProcessExtension {
+    fn get_source_info(&self) -> SourceInfo {
+        self.source_info.clone()
+    }
+
+    fn headers(&self) -> HashMap<String, String> {
+        self.call("headers", ()).unwrap_or_else(|e| {
+            error!("failed to get headers: {e}");
+            HashMap::new()
+        })
+    }
+
+    fn filter_list(&self) -> Vec<Input> {
+        self.call("filter_list", ()).unwrap_or_else(|e| {
+            error!("failed to get filter list: {e}");
+            vec![]
+        })
+    }
+
+    fn get_preferences(&self) -> Result<Vec<Input>> {
+        self.call("get_preferences", ())
+    }
+
+    fn set_preferences(&mut self, preferences: Vec<Input>) -> Result<()> {
+        self.call("set_preferences", &preferences)?;
+        self.preferences = Some(preferences);
+        Ok(())
+    }
+
+    fn get_popular_manga(&self, page: i64) -> Result<Vec<MangaInfo>> {
+        self.call("get_popular_manga", page)
+    }
+
+    fn get_latest_manga(&self, page: i64) -> Result<Vec<MangaInfo>> {
+        self.call("get_latest_manga", page)
+    }
+
+    fn search_manga(
+        &self,
+        page: i64,
+        query: Option<String>,
+        filters: Option<Vec<Input>>,
+    ) -> Result<Vec<MangaInfo>> {
+        self.call("search_manga", (page, query, filters))
+    }
+
+    fn get_manga_detail(&self, path: String) -> Result<MangaInfo> {
+        self.call("get_manga_detail", path)
+    }
+
+    fn get_chapters(&self, path: String) -> Result<Vec<ChapterInfo>> {
+        self.call("get_chapters", path)
+    }
+
+    fn get_pages(&self, path: String) -> Result<Vec<String>> {
+        self.call("get_pages", path)
+    }
+}
+
+/// Entrypoint of extension worker process spawned by `ProcessExtension`.
+///
+/// Loads the library at `path` and serves calls from stdin until it is closed,
+/// extension must not write to stdout. This blocks, run it outside of async runtime.
+pub fn run_worker<P: AsRef<Path>>(path: P) -> Result<()> {
+    let path = path.as_ref();
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout().lock();
+
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+    let mut source = match ExtensionManager::new(dir).load_file(path) {
+        Ok(source) => source,
+        Err(e) => {
+            write_frame(
+                &mut stdout,
+                &serde_json::to_vec(&response(Err(e.to_string())))?,
+            )?;
+            return Err(e);
+        }
+    };
+
+    let handshake = Handshake {
+        rustc_version: source.rustc_version.clone(),
+        lib_version: source.lib_version.clone(),
+        abi_version: source.abi_version,
+    };
+    write_frame(
+        &mut stdout,
+        &serde_json::to_vec(&response(Ok(serde_json::to_string(&handshake)?)))?,
+    )?;
+
+    let extension = source
+        .extension
+        .get_mut()
+        .ok_or_else(|| anyhow!("not initiated"))?;
+
+    loop {
+        let frame = match read_frame(&mut stdin) {
+            Ok(frame) => frame,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let req: Request = serde_json::from_slice(&frame)?;
+        let args = req.body.unwrap_or_else(|| "null".to_string());
+        let res = serde_json::from_slice::<Result<serde_json::Value, String>>(
+            &tanoshi_lib::ffi::call_method(extension, &req.method, &args),
+        )?
+        .and_then(|value| serde_json::to_string(&value).map_err(|e| e.to_string()));
+        write_frame(&mut stdout, &serde_json::to_vec(&response(res))?)?;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_frame() {
+        let mut buf = vec![];
+        let req = request("get_pages", serde_json::to_string("/chapter/1").unwrap());
+        write_frame(&mut buf, &serde_json::to_vec(&req).unwrap()).unwrap();
+
+        let req: Request =
+            serde_json::from_slice(&read_frame(&mut buf.as_slice()).unwrap()).unwrap();
+        assert_eq!(req.method, "get_pages");
+        assert_eq!(req.body.as_deref(), Some("\"/chapter/1\""));
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let ok = serde_json::to_vec(&response(Ok("[\"a\",\"b\"]".to_string()))).unwrap();
+        assert_eq!(parse_response::<Vec<String>>(&ok).unwrap(), vec!["a", "b"]);
+
+        let err = serde_json::to_vec(&response(Err("not found".to_string()))).unwrap();
+        assert_eq!(
+            parse_response::<Vec<String>>(&err).unwrap_err().to_string(),
+            "not found"
+        );
+    }
+}
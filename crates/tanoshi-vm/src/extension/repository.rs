@@ -0,0 +1,240 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+use bytes::Bytes;
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer, Verifier};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Integrity information of a single extension file in `index.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileIndex {
+    /// Hex encoded SHA-256 digest of the file
+    pub sha256: String,
+    /// Base64 encoded ed25519 signature of `{name}|{version}|{target}|{sha256}`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+impl FileIndex {
+    /// Hash `contents` and sign it together with the entry it belongs to if `keypair` is given
+    pub fn new(
+        name: &str,
+        version: &str,
+        target: &str,
+        contents: &[u8],
+        keypair: Option<&Keypair>,
+    ) -> Self {
+        let sha256 = hex::encode(Sha256::digest(contents));
+        let signature = keypair.map(|keypair| {
+            let message = signed_message(name, version, target, &sha256);
+            base64::encode(keypair.sign(message.as_bytes()).to_bytes())
+        });
+        Self { sha256, signature }
+    }
+
+    /// Check `contents` against the digest, and the signature if `public_key` is given.
+    /// The signature covers `name`, `version` and `target`, so a signed file can't be served
+    /// under another entry
+    pub fn verify(
+        &self,
+        name: &str,
+        version: &str,
+        target: &str,
+        contents: &[u8],
+        public_key: Option<&PublicKey>,
+    ) -> Result<()> {
+        let sha256 = hex::encode(Sha256::digest(contents));
+        if sha256 != self.sha256.to_lowercase() {
+            bail!("checksum mismatch, expected {}", self.sha256);
+        }
+
+        if let Some(public_key) = public_key {
+            let signature = self
+                .signature
+                .as_ref()
+                .ok_or_else(|| anyhow!("extension is not signed"))?;
+            let signature = Signature::from_bytes(&base64::decode(signature)?)?;
+            let message = signed_message(name, version, target, &sha256);
+            public_key
+                .verify(message.as_bytes(), &signature)
+                .map_err(|_| anyhow!("invalid signature"))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn signed_message(name: &str, version: &str, target: &str, sha256: &str) -> String {
+    format!("{}|{version}|{target}|{sha256}", name.to_lowercase())
+}
+
+/// An extension repository, a location with `index.json` and `{target}/{name}.{ext}` files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Repository {
+    pub name: String,
+    /// `http(s)://` url, `file://` url or path to a local directory
+    pub url: String,
+    /// Base64 encoded ed25519 public key, extensions from this repository must be signed by it
+    #[serde(default)]
+    pub public_key: Option<String>,
+}
+
+/// The part of an `index.json` entry needed to install an extension
+#[derive(Debug, Clone, Deserialize)]
+pub struct SourceIndex {
+    pub id: i64,
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub rustc_version: String,
+    #[serde(default)]
+    pub abi_version: Option<u32>,
+    /// Keyed by target, e.g. `x86_64-unknown-linux-gnu` or `wasm32-wasi`
+    #[serde(default)]
+    pub files: HashMap<String, FileIndex>,
+}
+
+/// Whether a native build can be loaded by this host, extensions using the stable interface
+/// only need matching abi version, otherwise `rustc` version has to be identical
+pub fn is_native_compatible(rustc_version: &str, abi_version: Option<u32>) -> bool {
+    match abi_version {
+        Some(abi_version) => abi_version == tanoshi_lib::ffi::ABI_VERSION,
+        None => rustc_version == tanoshi_lib::RUSTC_VERSION,
+    }
+}
+
+/// Decode base64 encoded ed25519 public key
+pub fn decode_public_key(public_key: &str) -> Result<PublicKey> {
+    Ok(PublicKey::from_bytes(&base64::decode(public_key.trim())?)?)
+}
+
+/// Decode base64 encoded ed25519 secret key
+pub fn decode_keypair(secret_key: &str) -> Result<Keypair> {
+    let secret = SecretKey::from_bytes(&base64::decode(secret_key.trim())?)?;
+    let public = PublicKey::from(&secret);
+    Ok(Keypair { secret, public })
+}
+
+/// Repository url can also be a local directory, either as `file://` url or plain path.
+/// Any other scheme than `http`, `https` and `file` is an error, e.g. a typo like `htps://`
+fn local_dir(repo_url: &str) -> Result<Option<PathBuf>> {
+    let (scheme, path) = match repo_url.split_once("://") {
+        Some(url) => url,
+        None => return Ok(Some(PathBuf::from(repo_url))),
+    };
+
+    match scheme.to_lowercase().as_str() {
+        "file" => Ok(Some(PathBuf::from(path))),
+        "http" | "https" => Ok(None),
+        _ => bail!("unsupported scheme {scheme:?} in repository url {repo_url}"),
+    }
+}
+
+/// Fetch `index.json` of a repository, `T` is usually `SourceIndex`
+pub async fn fetch_index<T: DeserializeOwned>(repo_url: &str) -> Result<Vec<T>> {
+    if let Some(dir) = local_dir(repo_url)? {
+        let index = tokio::fs::read(dir.join("index.json")).await?;
+        return Ok(serde_json::from_slice(&index)?);
+    }
+
+    Ok(reqwest::get(format!("{repo_url}/index.json"))
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+/// Fetch `{target}/{file_name}` of a repository, returns `None` if there is no such file
+pub async fn fetch_file(repo_url: &str, target: &str, file_name: &str) -> Result<Option<Bytes>> {
+    if let Some(dir) = local_dir(repo_url)? {
+        let path = dir.join(target).join(file_name);
+        info!("reading {}", path.display());
+
+        return match tokio::fs::read(&path).await {
+            Ok(contents) => Ok(Some(Bytes::from(contents))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        };
+    }
+
+    let url = format!("{repo_url}/{target}/{file_name}");
+    info!("downloading {}", url);
+
+    let res = reqwest::get(&url).await?;
+    if res.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    Ok(Some(res.error_for_status()?.bytes().await?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_local_dir() {
+        assert_eq!(
+            local_dir("file:///srv/extensions").unwrap(),
+            Some(PathBuf::from("/srv/extensions"))
+        );
+        assert_eq!(
+            local_dir("/srv/extensions").unwrap(),
+            Some(PathBuf::from("/srv/extensions"))
+        );
+        assert_eq!(
+            local_dir("extensions").unwrap(),
+            Some(PathBuf::from("extensions"))
+        );
+        assert_eq!(local_dir("https://example.com/repo").unwrap(), None);
+        assert_eq!(local_dir("HTTP://example.com/repo").unwrap(), None);
+        assert!(local_dir("htps://example.com/repo").is_err());
+        assert!(local_dir("ftp://example.com/repo").is_err());
+    }
+
+    #[test]
+    fn test_file_index_verify() {
+        let keypair = decode_keypair(&base64::encode([7u8; 32])).unwrap();
+        let contents = b"extension";
+        let file = FileIndex::new("Example", "0.1.0", "wasm32-wasi", contents, Some(&keypair));
+
+        assert!(file
+            .verify(
+                "example",
+                "0.1.0",
+                "wasm32-wasi",
+                contents,
+                Some(&keypair.public)
+            )
+            .is_ok());
+        assert!(file
+            .verify("example", "0.1.0", "wasm32-wasi", contents, None)
+            .is_ok());
+        assert!(file
+            .verify("example", "0.1.0", "wasm32-wasi", b"tampered", None)
+            .is_err());
+
+        // signed file served under another entry
+        for (name, version, target) in [
+            ("other", "0.1.0", "wasm32-wasi"),
+            ("example", "0.0.1", "wasm32-wasi"),
+            ("example", "0.1.0", "x86_64-unknown-linux-gnu"),
+        ] {
+            assert!(file
+                .verify(name, version, target, contents, Some(&keypair.public))
+                .is_err());
+        }
+
+        let unsigned = FileIndex::new("example", "0.1.0", "wasm32-wasi", contents, None);
+        assert!(unsigned
+            .verify(
+                "example",
+                "0.1.0",
+                "wasm32-wasi",
+                contents,
+                Some(&keypair.public)
+            )
+            .is_err());
+    }
+}
@@ -2,12 +2,17 @@ use libloading::Library;
 use once_cell::sync::OnceCell;
 use tanoshi_lib::prelude::Extension;
 
+use super::{FfiExtension, ProcessExtension, WasmExtension};
+
 pub struct Source {
     pub(crate) extension: OnceCell<Box<dyn Extension>>,
     #[allow(dead_code)]
     pub(crate) lib: Option<Library>,
     pub rustc_version: String,
     pub lib_version: String,
+    /// `tanoshi_lib::ffi::ABI_VERSION` the extension is loaded with, `None` for extensions
+    /// loaded through `PluginDeclaration`
+    pub abi_version: Option<u32>,
 }
 
 impl Source {
@@ -16,6 +21,7 @@ impl Source {
             lib: Some(lib),
             rustc_version: rustc_version.to_string(),
             lib_version: lib_version.to_string(),
+            abi_version: None,
             extension: OnceCell::new(),
         }
     }
@@ -25,9 +31,46 @@ impl Source {
             lib: None,
             rustc_version: tanoshi_lib::RUSTC_VERSION.to_string(),
             lib_version: tanoshi_lib::LIB_VERSION.to_string(),
+            abi_version: None,
             extension: OnceCell::from(extension),
         }
     }
+
+    pub fn from_wasm(extension: WasmExtension) -> Self {
+        Self {
+            lib: None,
+            rustc_version: crate::WASM_TARGET.to_string(),
+            lib_version: extension.lib_version.clone(),
+            abi_version: None,
+            extension: OnceCell::from(Box::new(extension) as Box<dyn Extension>),
+        }
+    }
+
+    pub fn from_process(extension: ProcessExtension) -> Self {
+        Self {
+            lib: None,
+            rustc_version: extension.rustc_version.clone(),
+            lib_version: extension.lib_version.clone(),
+            abi_version: extension.abi_version,
+            extension: OnceCell::from(Box::new(extension) as Box<dyn Extension>),
+        }
+    }
+
+    pub fn from_ffi(
+        lib: Library,
+        rustc_version: &str,
+        lib_version: &str,
+        extension: FfiExtension,
+    ) -> Self {
+        Self {
+            // extension has to be dropped before the library is unloaded, see field order
+            extension: OnceCell::from(Box::new(extension) as Box<dyn Extension>),
+            lib: Some(lib),
+            rustc_version: rustc_version.to_string(),
+            lib_version: lib_version.to_string(),
+            abi_version: Some(tanoshi_lib::ffi::ABI_VERSION),
+        }
+    }
 }
 
 impl tanoshi_lib::extensions::PluginRegistrar for Source {
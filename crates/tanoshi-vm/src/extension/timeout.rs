@@ -0,0 +1,274 @@
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use fnv::FnvHashMap;
+use serde::{Deserialize, Serialize};
+
+/// Extension operations with a deadline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    GetPopularManga,
+    GetLatestManga,
+    SearchManga,
+    GetMangaDetail,
+    GetChapters,
+    GetPages,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Operation::GetPopularManga => "get_popular_manga",
+            Operation::GetLatestManga => "get_latest_manga",
+            Operation::SearchManga => "search_manga",
+            Operation::GetMangaDetail => "get_manga_detail",
+            Operation::GetChapters => "get_chapters",
+            Operation::GetPages => "get_pages",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Returned when an extension doesn't finish an operation before its deadline,
+/// use `anyhow::Error::downcast_ref` to tell it apart from other errors
+#[derive(Debug, Clone)]
+pub struct TimeoutError {
+    pub source_id: i64,
+    pub operation: Operation,
+    pub timeout: Duration,
+}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "source {} timed out on {} after {}s",
+            self.source_id,
+            self.operation,
+            self.timeout.as_secs()
+        )
+    }
+}
+
+impl std::error::Error for TimeoutError {}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SourceTimeouts {
+    /// Seconds, overrides `Timeouts::default` for this source
+    #[serde(default)]
+    pub default: Option<u64>,
+    #[serde(default)]
+    pub operations: HashMap<Operation, u64>,
+}
+
+/// Deadlines in seconds, most specific one is used:
+/// source operation, source default, operation, then default
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Timeouts {
+    #[serde(default = "default_timeout")]
+    pub default: u64,
+    #[serde(default)]
+    pub operations: HashMap<Operation, u64>,
+    #[serde(default)]
+    pub sources: HashMap<i64, SourceTimeouts>,
+    /// Consecutive timeouts before a source is suspended
+    #[serde(default = "default_max_consecutive_timeouts")]
+    pub max_consecutive_timeouts: u32,
+    /// Seconds a source stays suspended
+    #[serde(default = "default_suspend_duration")]
+    pub suspend_duration: u64,
+}
+
+fn default_timeout() -> u64 {
+    120
+}
+
+fn default_max_consecutive_timeouts() -> u32 {
+    3
+}
+
+fn default_suspend_duration() -> u64 {
+    3600
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            default: default_timeout(),
+            operations: HashMap::new(),
+            sources: HashMap::new(),
+            max_consecutive_timeouts: default_max_consecutive_timeouts(),
+            suspend_duration: default_suspend_duration(),
+        }
+    }
+}
+
+impl Timeouts {
+    pub fn get(&self, source_id: i64, operation: Operation) -> Duration {
+        let source = self.sources.get(&source_id);
+        let secs = source
+            .and_then(|s| s.operations.get(&operation).copied())
+            .or_else(|| source.and_then(|s| s.default))
+            .or_else(|| self.operations.get(&operation).copied())
+            .unwrap_or(self.default);
+
+        Duration::from_secs(secs)
+    }
+}
+
+thread_local! {
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// Run `f` with `deadline` set for the extension call it makes on this thread,
+/// runtimes that can stop a call read it with `deadline`
+pub(crate) fn with_deadline<T>(deadline: Instant, f: impl FnOnce() -> T) -> T {
+    let previous = DEADLINE.with(|cell| cell.replace(Some(deadline)));
+    let res = f();
+    DEADLINE.with(|cell| cell.set(previous));
+    res
+}
+
+/// Deadline of the extension call running on this thread, if it is made by `ExtensionManager`
+pub(crate) fn deadline() -> Option<Instant> {
+    DEADLINE.with(|cell| cell.get())
+}
+
+/// Time left until `deadline`, or `default` if there is none
+pub(crate) fn remaining(default: Duration) -> Duration {
+    deadline()
+        .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+        .unwrap_or(default)
+}
+
+#[derive(Debug, Default)]
+struct TimeoutRecord {
+    consecutive: u32,
+    suspended_until: Option<Instant>,
+}
+
+/// Keeps count of consecutive timeouts per source
+#[derive(Debug, Default)]
+pub(crate) struct TimeoutTracker {
+    records: FnvHashMap<i64, TimeoutRecord>,
+}
+
+impl TimeoutTracker {
+    pub fn record_success(&mut self, source_id: i64) {
+        if let Some(record) = self.records.get_mut(&source_id) {
+            record.consecutive = 0;
+        }
+    }
+
+    pub fn record_timeout(&mut self, source_id: i64, timeouts: &Timeouts) {
+        let record = self.records.entry(source_id).or_default();
+        record.consecutive += 1;
+        if record.consecutive >= timeouts.max_consecutive_timeouts {
+            warn!(
+                "source {} timed out {} times in a row, suspend for {}s",
+                source_id, record.consecutive, timeouts.suspend_duration
+            );
+            record.consecutive = 0;
+            record.suspended_until =
+                Some(Instant::now() + Duration::from_secs(timeouts.suspend_duration));
+        }
+    }
+
+    pub fn is_suspended(&self, source_id: i64) -> bool {
+        self.records
+            .get(&source_id)
+            .and_then(|record| record.suspended_until)
+            .map(|until| until > Instant::now())
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_timeouts_get() {
+        let mut timeouts = Timeouts {
+            default: 120,
+            ..Default::default()
+        };
+        timeouts.operations.insert(Operation::GetPages, 30);
+        timeouts.sources.insert(
+            1,
+            SourceTimeouts {
+                default: Some(60),
+                operations: HashMap::from([(Operation::GetChapters, 10)]),
+            },
+        );
+        timeouts.sources.insert(
+            2,
+            SourceTimeouts {
+                default: None,
+                operations: HashMap::from([(Operation::SearchManga, 5)]),
+            },
+        );
+
+        let secs = |source_id, operation| timeouts.get(source_id, operation).as_secs();
+        // source operation, source default, operation, then default
+        assert_eq!(secs(1, Operation::GetChapters), 10);
+        assert_eq!(secs(1, Operation::GetPages), 60);
+        assert_eq!(secs(2, Operation::GetPages), 30);
+        assert_eq!(secs(2, Operation::SearchManga), 5);
+        assert_eq!(secs(2, Operation::GetChapters), 120);
+        assert_eq!(secs(3, Operation::GetPages), 30);
+        assert_eq!(secs(3, Operation::GetLatestManga), 120);
+    }
+
+    #[test]
+    fn test_timeout_tracker() {
+        let timeouts = Timeouts {
+            max_consecutive_timeouts: 3,
+            suspend_duration: 60,
+            ..Default::default()
+        };
+        let mut tracker = TimeoutTracker::default();
+
+        tracker.record_timeout(1, &timeouts);
+        tracker.record_timeout(1, &timeouts);
+        tracker.record_success(1);
+        tracker.record_timeout(1, &timeouts);
+        tracker.record_timeout(1, &timeouts);
+        assert!(!tracker.is_suspended(1));
+
+        tracker.record_timeout(1, &timeouts);
+        assert!(tracker.is_suspended(1));
+        assert!(!tracker.is_suspended(2));
+    }
+
+    #[test]
+    fn test_timeout_tracker_resume() {
+        let timeouts = Timeouts {
+            max_consecutive_timeouts: 1,
+            suspend_duration: 0,
+            ..Default::default()
+        };
+        let mut tracker = TimeoutTracker::default();
+
+        tracker.record_timeout(1, &timeouts);
+        assert!(!tracker.is_suspended(1));
+    }
+
+    #[test]
+    fn test_deadline() {
+        assert_eq!(deadline(), None);
+        assert_eq!(remaining(Duration::from_secs(5)), Duration::from_secs(5));
+
+        let at = Instant::now() + Duration::from_secs(10);
+        with_deadline(at, || {
+            assert_eq!(deadline(), Some(at));
+            assert!(remaining(Duration::from_secs(5)) > Duration::from_secs(5));
+        });
+        assert_eq!(deadline(), None);
+    }
+}
@@ -0,0 +1,494 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, Read, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use once_cell::sync::Lazy;
+use serde::{de::DeserializeOwned, Serialize};
+use tanoshi_lib::prelude::{ChapterInfo, Extension, Input, MangaInfo, SourceInfo};
+use tanoshi_util::http::Response;
+use wasi_common::pipe::{ReadPipe, WritePipe};
+use wasmtime::{
+    Caller, Config, Engine, Instance, Linker, Module, Store, StoreLimits, StoreLimitsBuilder,
+    TrapCode,
+};
+use wasmtime_wasi::{sync::WasiCtxBuilder, WasiCtx};
+
+use super::timeout::remaining;
+
+/// In-memory pipe shared between host and guest stdio
+#[derive(Clone, Default)]
+struct Pipe(Arc<Mutex<VecDeque<u8>>>);
+
+impl Pipe {
+    fn clear(&self) {
+        if let Ok(mut buf) = self.0.lock() {
+            buf.clear();
+        }
+    }
+
+    fn write_line(&self, line: &str) {
+        if let Ok(mut buf) = self.0.lock() {
+            buf.extend(line.as_bytes());
+            buf.push_back(b'\n');
+        }
+    }
+
+    fn read_line(&self) -> Option<String> {
+        let mut buf = self.0.lock().ok()?;
+        let pos = buf.iter().position(|b| *b == b'\n')?;
+        let line = buf.drain(..=pos).take(pos).collect::<Vec<u8>>();
+        Some(String::from_utf8_lossy(&line).to_string())
+    }
+}
+
+impl Read for Pipe {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let mut buf = self
+            .0
+            .lock()
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+        let len = out.len().min(buf.len());
+        for (dst, src) in out.iter_mut().zip(buf.drain(..len)) {
+            *dst = src;
+        }
+        Ok(len)
+    }
+}
+
+impl Write for Pipe {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0
+            .lock()
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?
+            .extend(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct State {
+    wasi: WasiCtx,
+    limits: StoreLimits,
+    stdin: Pipe,
+    stdout: Pipe,
+    stderr: Pipe,
+}
+
+struct Runtime {
+    store: Store<State>,
+    instance: Instance,
+    // a trapped instance may be left in any state, it is replaced before the next call
+    trapped: bool,
+}
+
+/// Interval the epoch of `wasm_engine` is incremented at, deadlines are rounded up to it
+const EPOCH_TICK: Duration = Duration::from_millis(100);
+/// Longest a call may run before it is interrupted, unless `ExtensionManager` sets a deadline
+const DEFAULT_DEADLINE: Duration = Duration::from_secs(60);
+/// Largest a linear memory of an extension may grow to, growing past it fails in the guest
+const MEMORY_LIMIT: usize = 256 << 20;
+
+static ENGINE: Lazy<Engine> = Lazy::new(|| {
+    let mut config = Config::new();
+    config.epoch_interruption(true);
+    let engine = Engine::new(&config).expect("failed to create wasm engine");
+
+    let ticker = engine.clone();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(EPOCH_TICK);
+        ticker.increment_epoch();
+    });
+
+    engine
+});
+
+/// Engine shared by all wasm extensions, calls running past their deadline are interrupted
+pub fn wasm_engine() -> Engine {
+    ENGINE.clone()
+}
+
+/// An extension compiled to `wasm32-wasi` and exported with `tanoshi_util::export_extension!`
+pub struct WasmExtension {
+    module: Module,
+    runtime: Mutex<Runtime>,
+    preferences: Option<Vec<Input>>,
+    source_info: SourceInfo,
+    pub(crate) lib_version: String,
+}
+
+fn link_host_functions(linker: &mut Linker<State>) -> Result<()> {
+    linker.func_wrap(
+        "tanoshi",
+        "host_http_request",
+        |caller: Caller<'_, State>| {
+            let state = caller.data();
+            let res = match state
+                .stdout
+                .read_line()
+                .ok_or_else(|| anyhow!("no request"))
+                .and_then(|line| ron::from_str(&line).map_err(|e| anyhow!("{e}")))
+                .and_then(|req| {
+                    let timeout = remaining(DEFAULT_DEADLINE);
+                    if timeout.is_zero() {
+                        bail!("request not sent before deadline");
+                    }
+                    Ok((req, timeout))
+                }) {
+                // the guest can't be interrupted while the host waits for a response, so the
+                // request has to give up at the deadline of the call
+                Ok((req, timeout)) => tanoshi_util::http::http_request_with_timeout(req, timeout),
+                Err(e) => Response {
+                    headers: HashMap::new(),
+                    body: format!("{e}"),
+                    status: 9999,
+                },
+            };
+
+            match ron::to_string(&res) {
+                Ok(res) => state.stdin.write_line(&res),
+                Err(e) => error!("failed to serialize response: {e}"),
+            }
+        },
+    )?;
+
+    for (name, level) in [
+        ("host_debug", log::Level::Debug),
+        ("host_error", log::Level::Error),
+        ("host_info", log::Level::Info),
+        ("host_trace", log::Level::Trace),
+        ("host_warn", log::Level::Warn),
+    ] {
+        linker.func_wrap("tanoshi", name, move |caller: Caller<'_, State>| {
+            while let Some(message) = caller.data().stderr.read_line() {
+                log!(target: "extension", level, "{}", message);
+            }
+        })?;
+    }
+
+    Ok(())
+}
+
+impl Runtime {
+    fn new(module: &Module) -> Result<Self> {
+        let engine = module.engine();
+
+        let mut linker = Linker::new(engine);
+        wasmtime_wasi::add_to_linker(&mut linker, |state: &mut State| &mut state.wasi)?;
+        link_host_functions(&mut linker)?;
+
+        let stdin = Pipe::default();
+        let stdout = Pipe::default();
+        let stderr = Pipe::default();
+        let wasi = WasiCtxBuilder::new()
+            .stdin(Box::new(ReadPipe::new(stdin.clone())))
+            .stdout(Box::new(WritePipe::new(stdout.clone())))
+            .stderr(Box::new(WritePipe::new(stderr.clone())))
+            .build();
+
+        let mut store = Store::new(
+            engine,
+            State {
+                wasi,
+                limits: StoreLimitsBuilder::new().memory_size(MEMORY_LIMIT).build(),
+                stdin,
+                stdout,
+                stderr,
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        store.set_epoch_deadline(epoch_ticks(DEFAULT_DEADLINE));
+        let instance = linker.instantiate(&mut store, module)?;
+
+        // modules built as wasi reactor need to run their constructors first
+        if let Ok(initialize) = instance.get_typed_func::<(), (), _>(&mut store, "_initialize") {
+            initialize.call(&mut store, ())?;
+        }
+
+        Ok(Self {
+            store,
+            instance,
+            trapped: false,
+        })
+    }
+
+    fn call<I: Serialize, O: DeserializeOwned>(
+        &mut self,
+        name: &str,
+        input: Option<I>,
+    ) -> Result<O> {
+        let state = self.store.data();
+        state.stdin.clear();
+        state.stdout.clear();
+        if let Some(input) = input {
+            state.stdin.write_line(&ron::to_string(&input)?);
+        }
+
+        let timeout = remaining(DEFAULT_DEADLINE);
+        if timeout.is_zero() {
+            bail!("{name} not started before its deadline");
+        }
+        self.store.set_epoch_deadline(epoch_ticks(timeout));
+
+        let func = self
+            .instance
+            .get_typed_func::<(), (), _>(&mut self.store, name)?;
+        if let Err(trap) = func.call(&mut self.store, ()) {
+            self.trapped = true;
+            if trap.trap_code() == Some(TrapCode::Interrupt) {
+                bail!("{name} interrupted after {}s", timeout.as_secs());
+            }
+            return Err(trap.into());
+        }
+
+        let mut output = None;
+        while let Some(line) = self.store.data().stdout.read_line() {
+            output = Some(line);
+        }
+        let output = output.ok_or_else(|| anyhow!("{name} returns nothing"))?;
+
+        ron::from_str::<Result<O, String>>(&output)?.map_err(|e| anyhow!(e))
+    }
+}
+
+fn epoch_ticks(timeout: Duration) -> u64 {
+    (timeout.as_millis() / EPOCH_TICK.as_millis()) as u64 + 1
+}
+
+impl WasmExtension {
+    pub fn load<P: AsRef<Path>>(engine: &Engine, path: P) -> Result<Self> {
+        Self::from_module(Module::from_file(engine, path)?)
+    }
+
+    fn from_module(module: Module) -> Result<Self> {
+        let mut runtime = Runtime::new(&module)?;
+
+        let lib_version: String = runtime.call("get_lib_version", None::<()>)?;
+        let source_info: SourceInfo = runtime.call("get_source_info", None::<()>)?;
+
+        Ok(Self {
+            module,
+            runtime: Mutex::new(runtime),
+            preferences: None,
+            source_info,
+            lib_version,
+        })
+    }
+
+    fn call<I: Serialize, O: DeserializeOwned>(&self, name: &str, input: Option<I>) -> Result<O> {
+        let mut runtime = self
+            .runtime
+            .lock()
+            .map_err(|e| anyhow!("failed to lock runtime: {e}"))?;
+        if runtime.trapped {
+            *runtime = self.instantiate()?;
+        }
+
+        runtime.call(name, input)
+    }
+
+    // a fresh instance has default preferences, the ones set before are applied again
+    fn instantiate(&self) -> Result<Runtime> {
+        let mut runtime = Runtime::new(&self.module)?;
+        if let Some(preferences) = &self.preferences {
+            runtime.call::<_, ()>("set_preferences", Some(preferences.clone()))?;
+        }
+
+        Ok(runtime)
+    }
+}
+
+impl Extension for WasmExtension {
+    fn get_source_info(&self) -> SourceInfo {
+        self.source_info.clone()
+    }
+
+    fn headers(&self) -> HashMap<String, String> {
+        self.call("headers", None::<()>).unwrap_or_else(|e| {
+            error!("failed to get headers: {e}");
+            HashMap::new()
+        })
+    }
+
+    fn filter_list(&self) -> Vec<Input> {
+        self.call("filter_list", None::<()>).unwrap_or_else(|e| {
+            error!("failed to get filter list: {e}");
+            vec![]
+        })
+    }
+
+    fn get_preferences(&self) -> Result<Vec<Input>> {
+        self.call("get_preferences", None::<()>)
+    }
+
+    fn set_preferences(&mut self, preferences: Vec<Input>) -> Result<()> {
+        self.call("set_preferences", Some(preferences.clone()))?;
+        self.preferences = Some(preferences);
+
+        Ok(())
+    }
+
+    fn get_popular_manga(&self, page: i64) -> Result<Vec<MangaInfo>> {
+        self.call("get_popular_manga", Some(page))
+    }
+
+    fn get_latest_manga(&self, page: i64) -> Result<Vec<MangaInfo>> {
+        self.call("get_latest_manga", Some(page))
+    }
+
+    fn search_manga(
+        &self,
+        page: i64,
+        query: Option<String>,
+        filters: Option<Vec<Input>>,
+    ) -> Result<Vec<MangaInfo>> {
+        self.call("search_manga", Some((page, query, filters)))
+    }
+
+    fn get_manga_detail(&self, path: String) -> Result<MangaInfo> {
+        self.call("get_manga_detail", Some(path))
+    }
+
+    fn get_chapters(&self, path: String) -> Result<Vec<ChapterInfo>> {
+        self.call("get_chapters", Some(path))
+    }
+
+    fn get_pages(&self, path: String) -> Result<Vec<String>> {
+        self.call("get_pages", Some(path))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::extension::timeout::with_deadline;
+
+    fn escape(s: &str) -> String {
+        s.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    }
+
+    // each function writes its output to stdout, `get_popular_manga` never returns and
+    // `get_latest_manga` traps when memory can't grow past `MEMORY_LIMIT`
+    fn module(functions: &[(&str, &str)]) -> Module {
+        let mut data = String::new();
+        let mut funcs = String::new();
+        // iovec at 0, written length at 8
+        let mut offset = 16;
+        for (name, output) in functions {
+            let line = format!("{output}\n");
+            data.push_str(&format!(
+                "  (data (i32.const {offset}) \"{}\")\n",
+                escape(&line)
+            ));
+            funcs.push_str(&format!(
+                r#"  (func (export "{name}")
+    (i32.store (i32.const 0) (i32.const {offset}))
+    (i32.store (i32.const 4) (i32.const {len}))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8))))
+"#,
+                len = line.len()
+            ));
+            offset += line.len();
+        }
+
+        let wat = format!(
+            r#"(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+{data}{funcs}  (func (export "get_popular_manga") (loop $forever (br $forever)))
+  (func (export "get_latest_manga")
+    (if (i32.eq (memory.grow (i32.const {pages})) (i32.const -1)) (then unreachable))))"#,
+            pages = MEMORY_LIMIT / 65536 + 1
+        );
+
+        Module::new(&wasm_engine(), wat).unwrap()
+    }
+
+    fn extension() -> WasmExtension {
+        WasmExtension::from_module(module(&[
+            ("get_lib_version", &format!("Ok(\"{}\")", tanoshi_lib::LIB_VERSION)),
+            (
+                "get_source_info",
+                r#"Ok((id:1,name:"Test",url:"https://example.com",version:"0.1.0",icon:"",languages:"en",nsfw:false))"#,
+            ),
+            ("get_pages", r#"Ok(["https://example.com/1.jpg"])"#),
+            ("get_chapters", r#"Err("not found")"#),
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_pipe() {
+        let pipe = Pipe::default();
+        pipe.write_line("first");
+        pipe.write_line("second");
+
+        assert_eq!(pipe.read_line().as_deref(), Some("first"));
+        assert_eq!(pipe.read_line().as_deref(), Some("second"));
+        assert_eq!(pipe.read_line(), None);
+    }
+
+    #[test]
+    fn test_call() {
+        let extension = extension();
+
+        assert_eq!(extension.lib_version, tanoshi_lib::LIB_VERSION);
+        assert_eq!(extension.get_source_info().name, "Test");
+        assert_eq!(
+            extension.get_pages("/chapter/1".to_string()).unwrap(),
+            vec!["https://example.com/1.jpg".to_string()]
+        );
+        assert_eq!(
+            extension
+                .get_chapters("/manga/1".to_string())
+                .unwrap_err()
+                .to_string(),
+            "not found"
+        );
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let extension = extension();
+
+        let deadline = Instant::now() + Duration::from_millis(200);
+        let err = with_deadline(deadline, || extension.get_popular_manga(1)).unwrap_err();
+        assert!(err.to_string().contains("interrupted"), "{err}");
+        assert!(Instant::now() >= deadline);
+        assert!(extension.runtime.lock().unwrap().trapped);
+
+        // trapped instance is replaced on next call
+        assert!(extension.get_pages("/chapter/1".to_string()).is_ok());
+        assert!(!extension.runtime.lock().unwrap().trapped);
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let extension = extension();
+
+        assert!(extension.get_latest_manga(1).is_err());
+        assert!(extension.runtime.lock().unwrap().trapped);
+    }
+
+    #[test]
+    fn test_deadline_passed() {
+        let extension = extension();
+
+        let err = with_deadline(Instant::now(), || {
+            extension.get_pages("/chapter/1".to_string())
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("deadline"), "{err}");
+    }
+}
@@ -10,3 +10,8 @@ pub const PLUGIN_EXTENSION: &str = "dll";
 pub const PLUGIN_EXTENSION: &str = "dylib";
 #[cfg(target_os = "linux")]
 pub const PLUGIN_EXTENSION: &str = "so";
+
+/// File extension of sandboxed WebAssembly extensions
+pub const WASM_EXTENSION: &str = "wasm";
+/// Repository target directory for WebAssembly extensions
+pub const WASM_TARGET: &str = "wasm32-wasi";
@@ -869,15 +869,15 @@ pub async fn resume_download() -> Result<bool, Box<dyn Error>> {
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "graphql/schema.graphql",
-    query_path = "graphql/download_status.graphql",
+    query_path = "graphql/download_paused.graphql",
     response_derives = "Debug"
 )]
-pub struct DownloadStatus;
+pub struct DownloadPaused;
 
-pub async fn download_status() -> Result<bool, Box<dyn Error>> {
-    let var = download_status::Variables {};
-    let data = post_graphql::<DownloadStatus>(var).await?;
-    Ok(data.download_status)
+pub async fn download_paused() -> Result<bool, Box<dyn Error>> {
+    let var = download_paused::Variables {};
+    let data = post_graphql::<DownloadPaused>(var).await?;
+    Ok(data.download_paused)
 }
 
 #[derive(GraphQLQuery)]
@@ -43,9 +43,9 @@ impl SettingsDownloads {
                     }
                 }
 
-                match query::download_status().await {
-                    Ok(status) => {
-                        settings.status.set(status);
+                match query::download_paused().await {
+                    Ok(paused) => {
+                        settings.status.set(!paused);
                     }
                     Err(err) => {
                         snackbar::show(format!("{}", err));
@@ -68,9 +68,9 @@ impl SettingsDownloads {
                     }
                 }
 
-                match query::download_status().await {
-                    Ok(status) => {
-                        settings.status.set(status);
+                match query::download_paused().await {
+                    Ok(paused) => {
+                        settings.status.set(!paused);
                     }
                     Err(err) => {
                         snackbar::show(format!("{}", err));
@@ -100,9 +100,10 @@ impl SettingsDownloads {
         AsyncLoader::new().load({
             let settings = self.clone();
             async move {
-                match query::download_status().await {
-                    Ok(status) => {
-                        settings.status.set(status);
+                // status is whether downloads are resumed, they may still wait for schedule
+                match query::download_paused().await {
+                    Ok(paused) => {
+                        settings.status.set(!paused);
                     }
                     Err(err) => {
                         snackbar::show(format!("{}", err));
@@ -12,7 +12,7 @@ use tanoshi::{
         tracker::TrackerService, user::UserService,
     },
     infrastructure::{
-        config::{self, Config},
+        config::Config,
         database,
         domain::repositories::{
             chapter::ChapterRepositoryImpl, download::DownloadRepositoryImpl,
@@ -27,13 +27,19 @@ use tanoshi::{
 };
 use tanoshi_notifier::{gotify::Gotify, pushover::Pushover, telegram::Telegram};
 use tanoshi_tracker::{AniList, MyAnimeList};
-use tanoshi_vm::{extension::ExtensionManager, prelude::Source};
+use tanoshi_vm::{
+    extension::{ExtensionManager, WorkerOptions},
+    prelude::Source,
+};
 
 #[derive(Parser)]
 struct Opts {
     /// Path to config file
     #[clap(long)]
     config: Option<String>,
+    /// Serve a single extension, used internally when `isolate_extensions` is enabled
+    #[clap(long, hide = true)]
+    extension_worker: Option<String>,
 }
 
 #[tokio::main]
@@ -51,6 +57,12 @@ async fn main() -> Result<(), anyhow::Error> {
     env_logger::init();
 
     let opts: Opts = Opts::parse();
+
+    if let Some(path) = opts.extension_worker {
+        return tokio::task::spawn_blocking(move || tanoshi_vm::extension::run_worker(path))
+            .await?;
+    }
+
     let config = Config::open(opts.config)?;
 
     debug!("config: {:?}", config);
@@ -61,7 +73,19 @@ async fn main() -> Result<(), anyhow::Error> {
     let user_repo = UserRepositoryImpl::new(pool.clone());
     let user_svc = UserService::new(user_repo.clone());
 
-    let extension_manager = ExtensionManager::new(&config.plugin_path);
+    let mut extension_manager = ExtensionManager::new(&config.plugin_path)
+        .with_timeouts(config.extension_timeouts.clone())
+        .with_versions_to_keep(config.extension_versions_to_keep);
+    if let Some(public_key) = &config.extension_public_key {
+        extension_manager = extension_manager
+            .with_public_key(tanoshi_vm::extension::decode_public_key(public_key)?);
+    }
+    if config.isolate_extensions {
+        extension_manager = extension_manager.with_worker(WorkerOptions {
+            program: std::env::current_exe()?,
+            args: vec!["--extension-worker".to_string()],
+        });
+    }
 
     extension_manager.load_all().await?;
 
@@ -80,29 +104,23 @@ async fn main() -> Result<(), anyhow::Error> {
     let history_repo = HistoryRepositoryImpl::new(pool.clone());
     let history_svc = HistoryService::new(chapter_repo.clone(), history_repo.clone());
 
-    match &config.local_path {
-        config::LocalFolders::Single(local_path) => {
-            extension_manager
-                .insert(Source::from(Box::new(local::Local::new(
-                    10000,
-                    "Local".to_string(),
-                    local_path,
-                ))))
-                .await?;
-        }
-        config::LocalFolders::Multiple(local_paths) => {
-            for (index, local_path) in local_paths.iter().enumerate() {
-                // source id starts from 10000
-                let index = index + 10000;
-                extension_manager
-                    .insert(Source::from(Box::new(local::Local::new(
-                        index as i64,
-                        local_path.name.clone(),
-                        &local_path.path,
-                    ))))
-                    .await?;
-            }
-        }
+    let mut local_indexes = vec![];
+    for (id, folder) in config.local_folders()? {
+        let local = local::Local::new(id, folder.name.clone(), &folder.path)
+            .with_max_depth(folder.max_depth.unwrap_or(config.local_max_depth))
+            .with_index_path(config.local_index_path(id))
+            .with_nsfw(folder.nsfw)
+            .with_languages(folder.languages.clone())
+            .with_file_filter(local::files::FileFilter::new(
+                &folder.include,
+                &folder.exclude,
+                folder.hidden,
+            )?)
+            .with_page_filter(local::files::PageFilter::new(&folder.exclude_pages)?);
+        local_indexes.push((id, local.index()));
+        extension_manager
+            .insert(Source::from(Box::new(local)))
+            .await?;
     }
 
     let mut notifier_builder = notification::Builder::new(user_repo.clone());
@@ -136,10 +154,14 @@ async fn main() -> Result<(), anyhow::Error> {
             chapter_repo.clone(),
             extension_manager.clone(),
             notifier.clone(),
-            config.extension_repository.clone(),
+            config.extension_repositories.clone(),
             &config.cache_path,
         );
 
+    if config.local_watch {
+        worker::local::start(local_indexes, chapter_update_command_tx.clone());
+    }
+
     let (download_sender, download_receiver) = worker::downloads::channel();
 
     let download_repo = DownloadRepositoryImpl::new(pool.clone());
@@ -156,6 +178,9 @@ async fn main() -> Result<(), anyhow::Error> {
         download_receiver,
         chapter_update_receiver.resubscribe(),
         config.auto_download_chapters,
+        config.local_downloads,
+        config.download_limits.clone(),
+        config.download_output.clone(),
     );
 
     let mal_client = if let Some(mal_cfg) = config.myanimelist.as_ref() {
@@ -0,0 +1,2 @@
+ALTER TABLE download_queue ADD COLUMN failed BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE download_queue ADD COLUMN error TEXT;
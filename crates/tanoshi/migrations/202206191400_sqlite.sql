@@ -0,0 +1,7 @@
+CREATE TABLE download_settings (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    paused BOOLEAN NOT NULL DEFAULT false,
+    schedule_start TIME,
+    schedule_end TIME
+);
+INSERT INTO download_settings (id, paused) VALUES (1, false);
@@ -8,6 +8,17 @@ use crate::server::Server;
 mod server;
 
 fn main() {
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  if let [flag, path] = args.as_slice() {
+    if flag == server::EXTENSION_WORKER_FLAG {
+      if let Err(e) = tanoshi_vm::extension::run_worker(path) {
+        eprintln!("extension worker failed: {}", e);
+        std::process::exit(1);
+      }
+      return;
+    }
+  }
+
   tauri::Builder::default()
     .plugin(Server::new())
     .run(tauri::generate_context!())
@@ -1,6 +1,7 @@
 // from https://github.com/tauri-apps/tauri-plugin-localhost
 
-use tanoshi_vm::prelude::{ExtensionManager, Source};
+use log::error;
+use tanoshi_vm::prelude::{ExtensionManager, Source, WorkerOptions};
 use tauri::{
   plugin::{Plugin, Result as PluginResult},
   AppHandle, Runtime,
@@ -14,7 +15,7 @@ use tanoshi::{
     tracker::TrackerService, user::UserService,
   },
   infrastructure::{
-    config::{self, Config},
+    config::Config,
     database,
     domain::repositories::{
       chapter::ChapterRepositoryImpl, download::DownloadRepositoryImpl,
@@ -29,6 +30,9 @@ use tanoshi::{
 };
 use tanoshi_tracker::{AniList, MyAnimeList};
 
+/// Argument the app is spawned with to serve a single extension, see `isolate_extensions`
+pub const EXTENSION_WORKER_FLAG: &str = "--extension-worker";
+
 pub struct Server {
   port: u16,
 }
@@ -57,7 +61,8 @@ impl<R: Runtime> Plugin<R> for Server {
 
       let pool = match database::establish_connection(&config.database_path, true).await {
         Ok(pool) => pool,
-        Err(_) => {
+        Err(e) => {
+          error!("failed to connect to database: {e}");
           return;
         }
       };
@@ -65,9 +70,31 @@ impl<R: Runtime> Plugin<R> for Server {
       let user_repo = UserRepositoryImpl::new(pool.clone());
       let user_svc = UserService::new(user_repo.clone());
 
-      let extension_manager = ExtensionManager::new(&config.plugin_path);
+      let mut extension_manager = ExtensionManager::new(&config.plugin_path)
+        .with_timeouts(config.extension_timeouts.clone())
+        .with_versions_to_keep(config.extension_versions_to_keep);
+      if let Some(public_key) = &config.extension_public_key {
+        match tanoshi_vm::extension::decode_public_key(public_key) {
+          Ok(public_key) => extension_manager = extension_manager.with_public_key(public_key),
+          // same as no key, only repositories with their own key are verified
+          Err(e) => error!("invalid extension_public_key, extensions are not verified: {e}"),
+        }
+      }
+      if config.isolate_extensions {
+        match std::env::current_exe() {
+          Ok(program) => {
+            extension_manager = extension_manager.with_worker(WorkerOptions {
+              program,
+              args: vec![EXTENSION_WORKER_FLAG.to_string()],
+            })
+          }
+          Err(e) => error!("failed to find executable, extensions run in process: {e}"),
+        }
+      }
 
-      let _ = extension_manager.load_all().await;
+      if let Err(e) = extension_manager.load_all().await {
+        error!("failed to load extensions: {e}");
+      }
 
       let source_repo = SourceRepositoryImpl::new(extension_manager.clone());
       let source_svc = SourceService::new(source_repo);
@@ -84,43 +111,63 @@ impl<R: Runtime> Plugin<R> for Server {
       let history_repo = HistoryRepositoryImpl::new(pool.clone());
       let history_svc = HistoryService::new(chapter_repo.clone(), history_repo.clone());
 
-      match &config.local_path {
-        config::LocalFolders::Single(local_path) => {
-          let _ = extension_manager
-            .insert(Source::from(Box::new(local::Local::new(
-              10000,
-              "Local".to_string(),
-              local_path,
-            ))))
-            .await;
+      let folders = match config.local_folders() {
+        Ok(folders) => folders,
+        Err(e) => {
+          error!("invalid local_path, local sources are not loaded: {e}");
+          vec![]
         }
-        config::LocalFolders::Multiple(local_paths) => {
-          for (index, local_path) in local_paths.iter().enumerate() {
-            // source id starts from 10000
-            let index = index + 10000;
-            let _ = extension_manager
-              .insert(Source::from(Box::new(local::Local::new(
-                index as i64,
-                local_path.name.clone(),
-                &local_path.path,
-              ))))
-              .await;
+      };
+      let mut local_indexes = vec![];
+      for (id, folder) in folders {
+        let filter =
+          match local::files::FileFilter::new(&folder.include, &folder.exclude, folder.hidden) {
+            Ok(filter) => filter,
+            Err(e) => {
+              error!("invalid glob pattern for local folder {}: {e}", folder.name);
+              continue;
+            }
+          };
+        let page_filter = match local::files::PageFilter::new(&folder.exclude_pages) {
+          Ok(page_filter) => page_filter,
+          Err(e) => {
+            error!(
+              "invalid exclude_pages pattern for local folder {}: {e}",
+              folder.name
+            );
+            continue;
           }
-        }
+        };
+        let local = local::Local::new(id, folder.name.clone(), &folder.path)
+          .with_max_depth(folder.max_depth.unwrap_or(config.local_max_depth))
+          .with_index_path(config.local_index_path(id))
+          .with_nsfw(folder.nsfw)
+          .with_languages(folder.languages.clone())
+          .with_file_filter(filter)
+          .with_page_filter(page_filter);
+        local_indexes.push((id, local.index()));
+        let _ = extension_manager
+          .insert(Source::from(Box::new(local)))
+          .await;
       }
 
       let notifier = notification::Builder::new(user_repo.clone()).finish();
 
-      let (chapter_update_receiver, _, update_worker_handle) = worker::updates::start(
+      let (chapter_update_receiver, chapter_update_command_tx, update_worker_handle) =
+        worker::updates::start(
         config.update_interval,
         library_repo.clone(),
         chapter_repo.clone(),
         extension_manager.clone(),
         notifier.clone(),
-        config.extension_repository.clone(),
+        config.extension_repositories.clone(),
         &config.cache_path,
       );
 
+      if config.local_watch {
+        worker::local::start(local_indexes, chapter_update_command_tx);
+      }
+
       let (download_sender, download_receiver) = worker::downloads::channel();
 
       let download_repo = DownloadRepositoryImpl::new(pool.clone());
@@ -137,6 +184,9 @@ impl<R: Runtime> Plugin<R> for Server {
         download_receiver,
         chapter_update_receiver.resubscribe(),
         config.auto_download_chapters,
+        config.local_downloads,
+        config.download_limits.clone(),
+        config.download_output.clone(),
       );
 
       let mal_client = config
@@ -187,7 +237,8 @@ impl<R: Runtime> Plugin<R> for Server {
 
       let server_fut = match server_builder.build() {
         Ok(server) => server.serve(([127, 0, 0, 1], port)),
-        Err(_) => {
+        Err(e) => {
+          error!("failed to build server: {e}");
           return;
         }
       };
@@ -5,41 +5,166 @@ use crate::{
             chapter::ChapterRepository, download::DownloadRepository, manga::MangaRepository,
         },
     },
-    infrastructure::{domain::repositories::user::UserRepositoryImpl, notification::Notification},
+    infrastructure::{
+        config::{DownloadLimits, DownloadOutput},
+        domain::repositories::user::UserRepositoryImpl,
+        notification::Notification,
+    },
 };
 use anyhow::{anyhow, Result};
-use chrono::Utc;
+use bytes::Bytes;
+use chrono::{Local, Utc};
 use reqwest::Url;
 use std::{
-    fs::File,
-    io::Write,
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
 };
 use tanoshi_vm::extension::ExtensionManager;
-use zip::{ZipArchive, ZipWriter};
 
 use tokio::{
-    sync::mpsc::{UnboundedReceiver, UnboundedSender},
+    sync::{
+        mpsc::{UnboundedReceiver, UnboundedSender},
+        OwnedSemaphorePermit, Semaphore,
+    },
     task::JoinHandle,
+    time::Instant,
 };
 
 use super::updates::ChapterUpdateReceiver;
 
+pub mod naming;
+pub mod output;
+pub mod verify;
+
+use naming::sanitize;
+use output::ChapterOutput;
+
 pub type DownloadSender = UnboundedSender<Command>;
 type DownloadReceiver = UnboundedReceiver<Command>;
 
+fn chapter_title(number: f64, title: &str) -> String {
+    format!("{} - {}", number, title)
+}
+
+/// Path of a chapter rendered from `directory` and `chapter` templates, without extension
+fn chapter_path(dir: &Path, output: &DownloadOutput, values: &[(&str, String)]) -> PathBuf {
+    dir.join(naming::render_path(
+        &format!("{}/{}", output.directory, output.chapter),
+        values,
+    ))
+}
+
+/// Completed output of a chapter in download directory, rendered for each of `source_names`.
+/// Another source may have a manga and chapter of the same title, so every source folder
+/// is only tried when the chapter's source is not loaded and its name is unknown
+fn find_downloaded(
+    dir: &Path,
+    output: &DownloadOutput,
+    source_names: &[String],
+    values: &[(&str, String)],
+) -> Option<PathBuf> {
+    source_names.iter().find_map(|source_name| {
+        let mut values = values.to_vec();
+        values.push(("source", source_name.clone()));
+        let path = output::output_path(output.format, &chapter_path(dir, output, &values));
+        if path.exists() {
+            Some(path)
+        } else {
+            None
+        }
+    })
+}
+
+// how often the schedule is checked to resume downloads
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Downloads are paused by user or outside of their schedule
+async fn paused<D: DownloadRepository>(download_repo: &D) -> bool {
+    match download_repo.get_download_settings().await {
+        Ok(settings) => !settings.is_active(Local::now().time()),
+        Err(e) => {
+            error!("failed to get download settings: {e}");
+            false
+        }
+    }
+}
+
+/// Spaces out page requests to a source
+struct RateLimiter {
+    interval: Duration,
+    next: tokio::sync::Mutex<Instant>,
+}
+
+impl RateLimiter {
+    fn new(requests_per_second: f64) -> Self {
+        let interval = if requests_per_second > 0.0 {
+            Duration::from_secs_f64(1.0 / requests_per_second)
+        } else {
+            Duration::ZERO
+        };
+
+        Self {
+            interval,
+            next: tokio::sync::Mutex::new(Instant::now()),
+        }
+    }
+
+    // the lock is held while sleeping so waiting requests go out one by one
+    async fn wait(&self) {
+        let mut next = self.next.lock().await;
+        tokio::time::sleep_until(*next).await;
+        *next = Instant::now() + self.interval;
+    }
+}
+
+#[derive(Clone)]
+struct SourceLimit {
+    semaphore: Arc<Semaphore>,
+    rate: Arc<RateLimiter>,
+}
+
+impl SourceLimit {
+    fn new(concurrency: usize, requests_per_second: f64) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(concurrency.max(1))),
+            rate: Arc::new(RateLimiter::new(requests_per_second)),
+        }
+    }
+}
+
+/// Permits to download a chapter, one from the pool and one from its source
+enum Permits {
+    Acquired(OwnedSemaphorePermit, OwnedSemaphorePermit),
+    PoolFull,
+    SourceFull,
+}
+
+fn acquire(pool: &Arc<Semaphore>, source: &SourceLimit) -> Permits {
+    let pool_permit = match pool.clone().try_acquire_owned() {
+        Ok(permit) => permit,
+        Err(_) => return Permits::PoolFull,
+    };
+    match source.semaphore.clone().try_acquire_owned() {
+        Ok(source_permit) => Permits::Acquired(pool_permit, source_permit),
+        Err(_) => Permits::SourceFull,
+    }
+}
+
 #[derive(Debug)]
 pub enum Command {
     InsertIntoQueue(i64),
     InsertIntoQueueBySourcePath(i64, String),
     Download,
+    ScanDownloads,
 }
 
 pub struct DownloadWorker<C, D, M>
 where
-    C: ChapterRepository + 'static,
-    D: DownloadRepository + 'static,
-    M: MangaRepository + 'static,
+    C: ChapterRepository + Clone + 'static,
+    D: DownloadRepository + Clone + 'static,
+    M: MangaRepository + Clone + 'static,
 {
     dir: PathBuf,
     client: reqwest::Client,
@@ -52,13 +177,37 @@ where
     rx: DownloadReceiver,
     chapter_update_receiver: ChapterUpdateReceiver,
     auto_download_chapter: bool,
+    link_downloads: bool,
+    limits: DownloadLimits,
+    output: DownloadOutput,
+    pool: Arc<Semaphore>,
+    sources: HashMap<i64, SourceLimit>,
+    in_progress: Arc<Mutex<HashSet<i64>>>,
+}
+
+/// Downloads pages of a single chapter into its output, runs in its own task
+struct ChapterDownloader<C, D, M>
+where
+    C: ChapterRepository + Clone + 'static,
+    D: DownloadRepository + Clone + 'static,
+    M: MangaRepository + Clone + 'static,
+{
+    dir: PathBuf,
+    client: reqwest::Client,
+    chapter_repo: C,
+    manga_repo: M,
+    download_repo: D,
+    ext: ExtensionManager,
+    output: DownloadOutput,
+    max_attempts: u32,
+    retry_delay: Duration,
 }
 
 impl<C, D, M> DownloadWorker<C, D, M>
 where
-    C: ChapterRepository + 'static,
-    D: DownloadRepository + 'static,
-    M: MangaRepository + 'static,
+    C: ChapterRepository + Clone + 'static,
+    D: DownloadRepository + Clone + 'static,
+    M: MangaRepository + Clone + 'static,
 {
     pub fn new<P: AsRef<Path>>(
         dir: P,
@@ -71,6 +220,9 @@ where
         download_receiver: DownloadReceiver,
         chapter_update_receiver: ChapterUpdateReceiver,
         auto_download_chapter: bool,
+        link_downloads: bool,
+        limits: DownloadLimits,
+        output: DownloadOutput,
     ) -> Self {
         Self {
             dir: PathBuf::new().join(dir),
@@ -84,6 +236,12 @@ where
             rx: download_receiver,
             chapter_update_receiver,
             auto_download_chapter,
+            link_downloads,
+            pool: Arc::new(Semaphore::new(limits.concurrency.max(1))),
+            limits,
+            output,
+            sources: HashMap::new(),
+            in_progress: Arc::new(Mutex::new(HashSet::new())),
         }
     }
 
@@ -118,7 +276,7 @@ where
                 manga_id: manga.id,
                 manga_title: manga.title.clone(),
                 chapter_id: chapter.id,
-                chapter_title: format!("{} - {}", chapter.number, chapter.title.clone()),
+                chapter_title: chapter_title(chapter.number, &chapter.title),
                 rank: rank as _,
                 url: page.clone(),
                 priority,
@@ -132,139 +290,217 @@ where
     }
 
     async fn paused(&self) -> bool {
-        self.dir.join(".pause").exists()
+        paused(&self.download_repo).await
     }
 
-    fn open_readable_zip_file<P: AsRef<Path>>(&self, archive_path: P) -> Result<ZipArchive<File>> {
-        let file = std::fs::OpenOptions::new().read(true).open(&archive_path)?;
-        Ok(zip::ZipArchive::new(file)?)
-    }
-
-    fn open_or_create_writeble_zip_file<P: AsRef<Path>>(
-        &self,
-        manga_path: P,
-        archive_path: P,
-    ) -> Result<ZipWriter<File>> {
-        if let Ok(file) = std::fs::OpenOptions::new()
-            .read(true)
-            .write(true)
-            .open(&archive_path)
-        {
-            return Ok(zip::ZipWriter::new_append(file)?);
-        } else if let Ok(file) =
-            std::fs::create_dir_all(manga_path).and_then(|_| std::fs::File::create(&archive_path))
-        {
-            return Ok(zip::ZipWriter::new(file));
+    // pause state used to be a `.pause` file in download directory
+    async fn migrate_pause_file(&self) -> Result<()> {
+        let pause_path = self.dir.join(".pause");
+        if pause_path.exists() {
+            self.download_repo.update_download_paused(true).await?;
+            std::fs::remove_file(pause_path)?;
         }
 
-        Err(anyhow!("cannot open or create new zip file"))
+        Ok(())
     }
 
-    async fn download(&mut self) -> Result<()> {
-        let mut queue = self
-            .download_repo
-            .get_single_download_queue()
-            .await?
-            .ok_or_else(|| anyhow!("no queue"))?;
-
-        debug!("got {}", queue.url);
-
-        let url = Url::parse(&queue.url)?;
-
-        let filename = url
-            .path_segments()
-            .and_then(|seg| seg.last())
-            .map(|s| s.to_string())
-            .ok_or_else(|| anyhow!("no filename"))?;
-
-        queue.source_name = queue
-            .source_name
-            .replace(&['\\', '/', ':', '*', '?', '\"', '<', '>', '|'][..], "");
-        queue.manga_title = queue
-            .manga_title
-            .replace(&['\\', '/', ':', '*', '?', '\"', '<', '>', '|'][..], "");
-        queue.chapter_title = queue
-            .chapter_title
-            .replace(&['\\', '/', ':', '*', '?', '\"', '<', '>', '|'][..], "");
+    fn source_limit(&mut self, source_id: i64) -> SourceLimit {
+        let limits = &self.limits;
+        self.sources
+            .entry(source_id)
+            .or_insert_with(|| {
+                SourceLimit::new(
+                    limits.source_concurrency(source_id),
+                    limits.source_requests_per_second(source_id),
+                )
+            })
+            .clone()
+    }
 
-        let manga_path = self.dir.join(&queue.source_name).join(&queue.manga_title);
+    /// Start downloading queued chapters in priority order until the pool or the limit
+    /// of their source is full, a finished chapter schedules the next ones
+    async fn download(&mut self) -> Result<()> {
+        let queue = self.download_repo.get_download_queue().await?;
+
+        for entry in queue {
+            if entry.failed
+                || self
+                    .in_progress
+                    .lock()
+                    .map_err(|e| anyhow!("{e}"))?
+                    .contains(&entry.chapter_id)
+            {
+                continue;
+            }
 
-        let archive_path = manga_path.join(format!("{}.cbz", queue.chapter_title));
+            let source = self.source_limit(entry.source_id);
+            let (pool_permit, source_permit) = match acquire(&self.pool, &source) {
+                Permits::Acquired(pool_permit, source_permit) => (pool_permit, source_permit),
+                Permits::PoolFull => break,
+                Permits::SourceFull => continue,
+            };
+
+            self.in_progress
+                .lock()
+                .map_err(|e| anyhow!("{e}"))?
+                .insert(entry.chapter_id);
+
+            let downloader = ChapterDownloader {
+                dir: self.dir.clone(),
+                client: self.client.clone(),
+                chapter_repo: self.chapter_repo.clone(),
+                manga_repo: self.manga_repo.clone(),
+                download_repo: self.download_repo.clone(),
+                ext: self.ext.clone(),
+                output: self.output.clone(),
+                max_attempts: self.limits.max_attempts.max(1),
+                retry_delay: Duration::from_secs(self.limits.retry_delay),
+            };
+            let in_progress = self.in_progress.clone();
+            let tx = self.tx.clone();
+            tokio::spawn(async move {
+                let chapter_id = entry.chapter_id;
+                // every page is marked downloaded when the chapter was interrupted before
+                // it was linked, it is finished instead of downloaded again
+                let finished = entry.downloaded >= entry.total;
+                if let Err(e) = downloader
+                    .download_chapter(chapter_id, entry.total as usize, finished, &source.rate)
+                    .await
+                {
+                    error!("failed to download chapter {chapter_id}: {e}");
+                    // failed chapters are skipped until retried
+                    if let Err(e) = downloader
+                        .download_repo
+                        .mark_chapter_download_queue_as_failed(chapter_id, &e.to_string())
+                        .await
+                    {
+                        error!("failed to mark chapter {chapter_id} as failed: {e}");
+                    }
+                }
+                drop(source_permit);
+                drop(pool_permit);
 
-        if let Ok(mut zip) = self.open_readable_zip_file(&archive_path) {
-            if zip.by_name(&filename).is_ok() {
-                debug!("file already downloaded, mark as compeleted then skip");
-                self.download_repo
-                    .mark_single_download_queue_as_completed(queue.id)
-                    .await?;
-                if !self.paused().await {
-                    self.tx.send(Command::Download).unwrap();
+                if let Ok(mut in_progress) = in_progress.lock() {
+                    in_progress.remove(&chapter_id);
                 }
-                return Ok(());
-            }
+                let _ = tx.send(Command::Download);
+            });
         }
 
-        let mut zip = self.open_or_create_writeble_zip_file(&manga_path, &archive_path)?;
-
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-
-        let referrer = self
-            .ext
-            .get_source_info(queue.source_id)
-            .map(|s| s.url)
-            .unwrap_or_default();
-
-        let contents = self
-            .client
-            .request(reqwest::Method::GET, url.clone())
-            .header("referer", referrer)
-            .send()
-            .await?
-            .bytes()
-            .await?;
-
-        zip.start_file(&filename, Default::default())?;
-
-        zip.write_all(contents.to_vec().as_slice())?;
-
-        self.download_repo
-            .mark_single_download_queue_as_completed(queue.id)
-            .await?;
+        Ok(())
+    }
 
-        if self
-            .download_repo
-            .get_single_chapter_download_status(queue.chapter_id)
-            .await
-            .unwrap_or_default()
-        {
-            self.download_repo
-                .update_chapter_downloaded_path(
-                    queue.chapter_id,
-                    Some(archive_path.display().to_string()),
-                )
-                .await?;
+    /// Set `downloaded_path` of chapters whose output is in download directory, e.g. after
+    /// the database is recreated. Outputs are found by rendering `download_output` for each
+    /// chapter, see `find_downloaded`.
+    async fn link_downloaded_chapters(&self) -> Result<usize> {
+        // folder names of sources, tried for chapters whose source is not loaded
+        let mut source_dirs: Vec<String> = self
+            .dir
+            .read_dir()?
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|name| !name.starts_with('.'))
+            .collect();
+        source_dirs.sort();
+
+        let mut linked = 0;
+        for chapter in self.download_repo.get_undownloaded_chapters().await? {
+            let source_names = match self.ext.get_source_info(chapter.source_id) {
+                Ok(source) => vec![source.name],
+                Err(_) => source_dirs.clone(),
+            };
+            let values = [
+                ("source_id", chapter.source_id.to_string()),
+                ("manga", chapter.manga_title.clone()),
+                ("manga_id", chapter.manga_id.to_string()),
+                ("number", chapter.chapter_number.to_string()),
+                ("title", chapter.chapter_title.clone()),
+                ("scanlator", chapter.chapter_scanlator.clone()),
+                ("chapter_id", chapter.chapter_id.to_string()),
+            ];
+            let path = match find_downloaded(&self.dir, &self.output, &source_names, &values) {
+                Some(path) => path.display().to_string(),
+                None => continue,
+            };
 
             self.download_repo
-                .delete_single_chapter_download_queue(queue.chapter_id)
+                .update_chapter_downloaded_path(chapter.chapter_id, Some(path))
                 .await?;
+            linked += 1;
         }
 
-        zip.flush()?;
+        Ok(linked)
+    }
+
+    /// Verify every downloaded chapter, broken or incomplete ones are queued to download
+    /// again. Their download is kept until replaced, and chapters whose source is not loaded
+    /// are only reported as they can't be downloaded. Returns number of queued chapters.
+    async fn scan_downloads(&mut self) -> Result<usize> {
+        let mut requeued = 0;
+        for chapter in self.download_repo.get_all_downloaded_chapters().await? {
+            let path = match &chapter.downloaded_path {
+                Some(downloaded_path) => PathBuf::from(downloaded_path),
+                None => continue,
+            };
+
+            let res = tokio::task::spawn_blocking(move || verify::verify_chapter(&path)).await?;
+            let e = match res {
+                Ok(_) => continue,
+                Err(e) => e,
+            };
+            if self.ext.get_source_info(chapter.source_id).is_err() {
+                warn!(
+                    "downloaded chapter {} is broken, source {} is not loaded: {e}",
+                    chapter.id, chapter.source_id
+                );
+                continue;
+            }
+            warn!("downloaded chapter {} is broken: {e}", chapter.id);
 
-        if !self.paused().await {
-            self.tx.send(Command::Download).unwrap();
+            let chapter_id = chapter.id;
+            let res = async {
+                let chapter = self.chapter_repo.get_chapter_by_id(chapter_id).await?;
+                self.insert_to_queue(&chapter).await
+            }
+            .await;
+            match res {
+                Ok(_) => requeued += 1,
+                Err(e) => error!("failed to queue chapter {chapter_id}: {e}"),
+            }
         }
 
-        Ok(())
+        if requeued > 0 {
+            let _ = self.tx.send(Command::Download);
+        }
+
+        Ok(requeued)
     }
 
     pub async fn run(mut self) {
-        if !self.paused().await {
-            self.tx.send(Command::Download).unwrap();
+        if self.link_downloads {
+            match self.link_downloaded_chapters().await {
+                Ok(linked) => info!("linked {linked} downloaded chapters"),
+                Err(e) => error!("failed to link downloaded chapters: {e}"),
+            }
+        }
+
+        if let Err(e) = self.migrate_pause_file().await {
+            error!("failed to migrate pause file: {e}");
         }
 
+        let mut schedule_interval = tokio::time::interval(SCHEDULE_INTERVAL);
+
         loop {
             tokio::select! {
+                _ = schedule_interval.tick() => {
+                    if !self.paused().await {
+                        if let Err(e) = self.download().await {
+                            error!("{e}")
+                        }
+                    }
+                }
                 Ok(chapter) = self.chapter_update_receiver.recv() => {
                     debug!("update: {chapter:?}");
                     if self.auto_download_chapter {
@@ -316,6 +552,10 @@ where
                                 }
                             }
                         }
+                        Command::ScanDownloads => match self.scan_downloads().await {
+                            Ok(requeued) => info!("queued {requeued} broken downloaded chapters"),
+                            Err(e) => error!("failed to scan downloads: {e}"),
+                        },
                     }
                 }
             }
@@ -323,6 +563,210 @@ where
     }
 }
 
+impl<C, D, M> ChapterDownloader<C, D, M>
+where
+    C: ChapterRepository + Clone + 'static,
+    D: DownloadRepository + Clone + 'static,
+    M: MangaRepository + Clone + 'static,
+{
+    async fn download_chapter(
+        &self,
+        chapter_id: i64,
+        page_count: usize,
+        finished: bool,
+        rate: &RateLimiter,
+    ) -> Result<()> {
+        let pages = self
+            .download_repo
+            .get_chapter_download_queue(chapter_id)
+            .await?;
+        let first = match pages.first() {
+            Some(first) => first,
+            None => return Ok(()),
+        };
+
+        let chapter = self.chapter_repo.get_chapter_by_id(chapter_id).await?;
+        let manga = self.manga_repo.get_manga_by_id(chapter.manga_id).await?;
+        let values = [
+            ("source", first.source_name.clone()),
+            ("source_id", first.source_id.to_string()),
+            ("manga", manga.title.clone()),
+            ("manga_id", manga.id.to_string()),
+            ("number", chapter.number.to_string()),
+            ("title", chapter.title.clone()),
+            ("scanlator", chapter.scanlator.clone()),
+            ("chapter_id", chapter.id.to_string()),
+        ];
+        let path = chapter_path(&self.dir, &self.output, &values);
+
+        let output_path = output::output_path(self.output.format, &path);
+        if finished && output_path.exists() {
+            return self.link_chapter(&chapter, &output_path).await;
+        }
+
+        let referrer = self
+            .ext
+            .get_source_info(first.source_id)
+            .map(|s| s.url)
+            .unwrap_or_default();
+
+        let mut chapter_output = ChapterOutput::open(self.output.format, &path)?;
+        let mut completed = vec![];
+        let res = self
+            .write_pages(&mut chapter_output, &pages, &referrer, rate, &mut completed)
+            .await;
+
+        // an incomplete chapter is left staged, the next run picks up the remaining pages
+        let downloaded_path = if res.is_ok() && completed.len() == pages.len() {
+            let comic_info = output::comic_info(&manga, &chapter, page_count);
+            Some(chapter_output.complete(&comic_info)?)
+        } else {
+            None
+        };
+
+        for id in completed {
+            self.download_repo
+                .mark_single_download_queue_as_completed(id)
+                .await?;
+        }
+        res?;
+
+        if let Some(downloaded_path) = downloaded_path {
+            self.link_chapter(&chapter, &downloaded_path).await?;
+        }
+
+        Ok(())
+    }
+
+    /// A chapter downloaded again replaces its previous download, which is removed if the
+    /// naming has changed since
+    async fn link_chapter(&self, chapter: &Chapter, downloaded_path: &Path) -> Result<()> {
+        self.download_repo
+            .update_chapter_downloaded_path(chapter.id, Some(downloaded_path.display().to_string()))
+            .await?;
+
+        self.download_repo
+            .delete_single_chapter_download_queue(chapter.id)
+            .await?;
+
+        if let Some(previous) = chapter.downloaded_path.as_deref().map(Path::new) {
+            if previous != downloaded_path {
+                if let Err(e) = output::remove_output(previous) {
+                    error!("failed to remove {}: {e}", previous.display());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Retry with exponential backoff until `max_attempts` is reached, a page that is not
+    /// an image counts as a failed attempt. Returns the page and its extension.
+    async fn fetch_page(
+        &self,
+        url: Url,
+        referrer: &str,
+        rate: &RateLimiter,
+    ) -> Result<(Bytes, &'static str)> {
+        let mut attempt = 1;
+        loop {
+            rate.wait().await;
+
+            let res = async {
+                let res = self
+                    .client
+                    .request(reqwest::Method::GET, url.clone())
+                    .header("referer", referrer)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+                let content_type = res
+                    .headers()
+                    .get(reqwest::header::CONTENT_TYPE)
+                    .and_then(|content_type| content_type.to_str().ok())
+                    .map(|content_type| content_type.to_string());
+                let contents = res.bytes().await?;
+                let extension = verify::validate_page(content_type.as_deref(), &contents)?;
+
+                Ok::<_, anyhow::Error>((contents, extension))
+            }
+            .await;
+
+            match res {
+                Ok(page) => return Ok(page),
+                Err(e) if attempt < self.max_attempts => {
+                    let delay = self
+                        .retry_delay
+                        .saturating_mul(2_u32.saturating_pow(attempt - 1));
+                    debug!("failed to download {url}, retry in {delay:?}: {e}");
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    return Err(anyhow!(
+                        "failed to download {url} after {attempt} attempts: {e}"
+                    ))
+                }
+            }
+        }
+    }
+
+    // pages are named by template instead of url, different urls often end with same name.
+    // Extension is left out, it is taken from the image once downloaded.
+    fn page_name(&self, page: &DownloadQueue, url: &Url) -> String {
+        let filename = url
+            .path_segments()
+            .and_then(|seg| seg.last())
+            .unwrap_or_default();
+        let stem = match filename.rsplit_once('.') {
+            Some((stem, _)) if !stem.is_empty() => stem,
+            _ => filename,
+        };
+
+        let name = naming::render(
+            &self.output.page,
+            &[
+                ("rank", page.rank.to_string()),
+                ("filename", stem.to_string()),
+            ],
+        );
+        sanitize(&name)
+    }
+
+    async fn write_pages(
+        &self,
+        chapter_output: &mut ChapterOutput,
+        pages: &[DownloadQueue],
+        referrer: &str,
+        rate: &RateLimiter,
+        completed: &mut Vec<i64>,
+    ) -> Result<()> {
+        for page in pages {
+            if paused(&self.download_repo).await {
+                break;
+            }
+
+            debug!("got {}", page.url);
+
+            let url = Url::parse(&page.url)?;
+            let name = self.page_name(page, &url);
+
+            if chapter_output.contains(&name) {
+                debug!("file already downloaded, mark as compeleted then skip");
+                completed.push(page.id);
+                continue;
+            }
+
+            let (contents, extension) = self.fetch_page(url, referrer, rate).await?;
+
+            chapter_output.write_page(&name, extension, &contents)?;
+            completed.push(page.id);
+        }
+
+        Ok(())
+    }
+}
+
 pub fn channel() -> (DownloadSender, DownloadReceiver) {
     tokio::sync::mpsc::unbounded_channel::<Command>()
 }
@@ -338,11 +782,14 @@ pub fn start<C, D, M, P>(
     download_receiver: DownloadReceiver,
     chapter_update_receiver: ChapterUpdateReceiver,
     auto_download_chapter: bool,
+    link_downloads: bool,
+    limits: DownloadLimits,
+    output: DownloadOutput,
 ) -> JoinHandle<()>
 where
-    C: ChapterRepository + 'static,
-    D: DownloadRepository + 'static,
-    M: MangaRepository + 'static,
+    C: ChapterRepository + Clone + 'static,
+    D: DownloadRepository + Clone + 'static,
+    M: MangaRepository + Clone + 'static,
     P: AsRef<Path>,
 {
     let download_worker = DownloadWorker::new(
@@ -356,7 +803,129 @@ where
         download_receiver,
         chapter_update_receiver,
         auto_download_chapter,
+        link_downloads,
+        limits,
+        output,
     );
 
     tokio::spawn(download_worker.run())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::infrastructure::config::DownloadFormat;
+
+    #[test]
+    fn test_find_downloaded() {
+        let dir =
+            std::env::temp_dir().join(format!("tanoshi-find-downloaded-{}", std::process::id()));
+        let manga_dir = |source: &str| dir.join(source).join("Manga");
+        std::fs::create_dir_all(manga_dir("Source A")).unwrap();
+        std::fs::create_dir_all(manga_dir("Source B")).unwrap();
+        std::fs::write(manga_dir("Source A").join("1 - Title.cbz"), b"").unwrap();
+        std::fs::write(manga_dir("Source B").join("1 - Title.cbz"), b"").unwrap();
+        std::fs::create_dir_all(manga_dir("Source B").join("0001 Title")).unwrap();
+
+        let values = [
+            ("manga", "Manga".to_string()),
+            ("number", "1".to_string()),
+            ("title", "Title".to_string()),
+        ];
+        let names = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        };
+        let cbz = DownloadOutput::default();
+
+        assert_eq!(
+            find_downloaded(&dir, &cbz, &names(&["Source B"]), &values),
+            Some(manga_dir("Source B").join("1 - Title.cbz"))
+        );
+        // same title downloaded from another source
+        assert_eq!(
+            find_downloaded(&dir, &cbz, &names(&["Source C"]), &values),
+            None
+        );
+        // source is not loaded
+        assert_eq!(
+            find_downloaded(&dir, &cbz, &names(&["Source A", "Source B"]), &values),
+            Some(manga_dir("Source A").join("1 - Title.cbz"))
+        );
+        assert_eq!(find_downloaded(&dir, &cbz, &[], &values), None);
+
+        let folder = DownloadOutput {
+            format: DownloadFormat::Folder,
+            chapter: "{number:04} {title}".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            find_downloaded(&dir, &folder, &names(&["Source A", "Source B"]), &values),
+            Some(manga_dir("Source B").join("0001 Title"))
+        );
+        let epub = DownloadOutput {
+            format: DownloadFormat::Epub,
+            ..Default::default()
+        };
+        assert_eq!(
+            find_downloaded(&dir, &epub, &names(&["Source A"]), &values),
+            None
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_acquire() {
+        let pool = Arc::new(Semaphore::new(2));
+        let source_a = SourceLimit::new(1, 0.0);
+        let source_b = SourceLimit::new(1, 0.0);
+
+        let first = acquire(&pool, &source_a);
+        assert!(matches!(first, Permits::Acquired(..)));
+        // source permit is taken, the pool permit is given back
+        assert!(matches!(acquire(&pool, &source_a), Permits::SourceFull));
+        assert_eq!(pool.available_permits(), 1);
+
+        let second = acquire(&pool, &source_b);
+        assert!(matches!(second, Permits::Acquired(..)));
+        assert!(matches!(acquire(&pool, &source_b), Permits::PoolFull));
+
+        drop(first);
+        assert!(matches!(acquire(&pool, &source_a), Permits::Acquired(..)));
+    }
+
+    #[test]
+    fn test_source_limit_concurrency() {
+        // zero would never download anything
+        assert_eq!(SourceLimit::new(0, 0.0).semaphore.available_permits(), 1);
+        assert_eq!(SourceLimit::new(3, 0.0).semaphore.available_permits(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter() {
+        let rate = RateLimiter::new(20.0);
+        assert_eq!(rate.interval, Duration::from_millis(50));
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            rate.wait().await;
+        }
+        // first request goes out immediately
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter_unlimited() {
+        let rate = RateLimiter::new(0.0);
+        assert_eq!(rate.interval, Duration::ZERO);
+
+        let start = Instant::now();
+        for _ in 0..100 {
+            rate.wait().await;
+        }
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,104 @@
+use std::path::PathBuf;
+
+// characters not allowed in file names on some platforms
+pub fn sanitize(name: &str) -> String {
+    name.replace(&['\\', '/', ':', '*', '?', '\"', '<', '>', '|'][..], "")
+}
+
+fn pad(value: &str, width: Option<usize>) -> String {
+    let width = match width {
+        Some(width) => width,
+        None => return value.to_string(),
+    };
+
+    let (integer, fraction) = match value.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (value, None),
+    };
+    if integer.is_empty() || !integer.chars().all(|c| c.is_ascii_digit()) {
+        return value.to_string();
+    }
+
+    match fraction {
+        Some(fraction) => format!("{integer:0>width$}.{fraction}"),
+        None => format!("{integer:0>width$}"),
+    }
+}
+
+/// Fill `{name}` placeholders of `template` with `values`, `{name:03}` pads the integer part
+/// of a number with zeros. Unknown placeholders are kept as is.
+pub fn render(template: &str, values: &[(&str, String)]) -> String {
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        rendered.push_str(&rest[..start]);
+
+        let placeholder = &rest[start + 1..end];
+        let (name, width) = match placeholder.split_once(':') {
+            Some((name, width)) => (name, width.parse().ok()),
+            None => (placeholder, None),
+        };
+        match values.iter().find(|(key, _)| *key == name) {
+            Some((_, value)) => rendered.push_str(&pad(value, width)),
+            None => rendered.push_str(&rest[start..=end]),
+        }
+
+        rest = &rest[end + 1..];
+    }
+    rendered.push_str(rest);
+
+    rendered
+}
+
+/// Render a path template, values are sanitized so only `/` of the template separates folders
+pub fn render_path(template: &str, values: &[(&str, String)]) -> PathBuf {
+    let values: Vec<(&str, String)> = values
+        .iter()
+        .map(|(key, value)| (*key, sanitize(value)))
+        .collect();
+
+    render(template, &values)
+        .split('/')
+        .map(str::trim)
+        .filter(|component| !component.is_empty() && *component != "." && *component != "..")
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let values = [
+            ("number", "1.5".to_string()),
+            ("rank", "7".to_string()),
+            ("title", "Chapter 1".to_string()),
+        ];
+
+        assert_eq!(render("{number} - {title}", &values), "1.5 - Chapter 1");
+        assert_eq!(render("{number:03}", &values), "001.5");
+        assert_eq!(render("{rank:03}", &values), "007");
+        assert_eq!(render("{title:03}", &values), "Chapter 1");
+        assert_eq!(render("{unknown} {rank", &values), "{unknown} {rank");
+    }
+
+    #[test]
+    fn test_render_path() {
+        let values = [
+            ("source", "Source".to_string()),
+            ("manga", "../Who: Me?".to_string()),
+        ];
+
+        assert_eq!(
+            render_path("{source}//{manga}/./", &values),
+            Path::new("Source").join("..Who Me")
+        );
+    }
+}
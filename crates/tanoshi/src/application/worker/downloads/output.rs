@@ -0,0 +1,431 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{Datelike, Utc};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    domain::entities::{chapter::Chapter, manga::Manga},
+    infrastructure::{
+        config::DownloadFormat,
+        local::{
+            comicinfo::{self, escape},
+            files::compare_pages,
+        },
+    },
+};
+
+/// `ComicInfo.xml` embedded in downloaded chapters so other readers can show their metadata
+pub fn comic_info(manga: &Manga, chapter: &Chapter, page_count: usize) -> String {
+    comicinfo::build_xml(&[
+        ("Title", chapter.title.clone()),
+        ("Series", manga.title.clone()),
+        ("Number", chapter.number.to_string()),
+        ("Summary", manga.description.clone().unwrap_or_default()),
+        ("Year", chapter.uploaded.year().to_string()),
+        ("Month", chapter.uploaded.month().to_string()),
+        ("Day", chapter.uploaded.day().to_string()),
+        ("Writer", manga.author.join(", ")),
+        ("Genre", manga.genre.join(", ")),
+        ("Status", manga.status.clone().unwrap_or_default()),
+        ("ScanInformation", chapter.scanlator.clone()),
+        ("PageCount", page_count.to_string()),
+    ])
+}
+
+// `Path::with_extension` would replace anything after a dot in chapter name, e.g. `1.5 - Title`
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+/// Path of a completed chapter rendered to `path`, with the extension of `format`
+pub fn output_path(format: DownloadFormat, path: &Path) -> PathBuf {
+    match format {
+        DownloadFormat::Cbz => with_extension(path, "cbz"),
+        DownloadFormat::Folder => path.to_path_buf(),
+        DownloadFormat::Epub => with_extension(path, "epub"),
+    }
+}
+
+/// Remove a completed chapter, a chapter downloaded as folder of images is a directory
+pub fn remove_output(path: &Path) -> Result<()> {
+    if path.is_dir() {
+        std::fs::remove_dir_all(path)?;
+    } else if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+
+    Ok(())
+}
+
+fn stored() -> FileOptions {
+    FileOptions::default().compression_method(CompressionMethod::Stored)
+}
+
+/// Pages of a chapter are downloaded into a hidden folder next to the chapter and moved into
+/// place once all of them are there, so a chapter is never left half written
+pub struct ChapterOutput {
+    format: DownloadFormat,
+    path: PathBuf,
+    staging: PathBuf,
+    // pages by name without extension, the extension comes from the image itself
+    pages: HashSet<String>,
+}
+
+fn page_stem(name: &str) -> &str {
+    name.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(name)
+}
+
+impl ChapterOutput {
+    /// Output of a chapter at `path`, the extension of `format` is added
+    pub fn open(format: DownloadFormat, path: &Path) -> Result<Self> {
+        let path = output_path(format, path);
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| anyhow!("{} has no file name", path.display()))?
+            .to_string_lossy()
+            .to_string();
+        let staging = path.with_file_name(format!(".{file_name}.parts"));
+        std::fs::create_dir_all(&staging)?;
+
+        let pages = staging
+            .read_dir()?
+            .filter_map(Result::ok)
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|name| !name.ends_with(".part"))
+            .map(|name| page_stem(&name).to_string())
+            .collect();
+
+        Ok(Self {
+            format,
+            path,
+            staging,
+            pages,
+        })
+    }
+
+    /// Whether page `name` was downloaded by an earlier run
+    pub fn contains(&self, name: &str) -> bool {
+        self.pages.contains(name)
+    }
+
+    /// Page is written under another name first so an interrupted write is not taken as a page
+    pub fn write_page(&mut self, name: &str, extension: &str, data: &[u8]) -> Result<()> {
+        let page = self.staging.join(format!("{name}.{extension}"));
+        let part = self.staging.join(format!("{name}.{extension}.part"));
+        std::fs::write(&part, data)?;
+        std::fs::rename(part, page)?;
+        self.pages.insert(name.to_string());
+
+        Ok(())
+    }
+
+    // sorted by name since pages are named by rank
+    fn pages(&self) -> Result<Vec<String>> {
+        let mut pages: Vec<String> = self
+            .staging
+            .read_dir()?
+            .filter_map(Result::ok)
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|name| name != comicinfo::FILE_NAME && !name.ends_with(".part"))
+            .collect();
+        pages.sort_by(|a, b| compare_pages(a, b));
+
+        Ok(pages)
+    }
+
+    /// All pages are written, move the chapter into place and return its path
+    pub fn complete(self, comic_info: &str) -> Result<PathBuf> {
+        match self.format {
+            DownloadFormat::Cbz => self.write_archive(comic_info, Self::write_cbz)?,
+            DownloadFormat::Epub => self.write_archive(comic_info, Self::write_epub)?,
+            DownloadFormat::Folder => {
+                std::fs::write(self.staging.join(comicinfo::FILE_NAME), comic_info)?;
+                // a folder can't replace another one, an older download is removed first
+                if self.path.exists() {
+                    std::fs::remove_dir_all(&self.path)?;
+                }
+                std::fs::rename(&self.staging, &self.path)?;
+                return Ok(self.path);
+            }
+        }
+
+        std::fs::remove_dir_all(&self.staging)?;
+
+        Ok(self.path)
+    }
+
+    fn write_archive(
+        &self,
+        comic_info: &str,
+        write: fn(&Self, &mut ZipWriter<File>, &str) -> Result<()>,
+    ) -> Result<()> {
+        let tmp = with_extension(&self.path, "tmp");
+        let mut zip = ZipWriter::new(File::create(&tmp)?);
+        let res = write(self, &mut zip, comic_info).and_then(|_| Ok(zip.finish()?));
+        if let Err(e) = res {
+            let _ = std::fs::remove_file(&tmp);
+            return Err(e);
+        }
+
+        std::fs::rename(&tmp, &self.path)?;
+
+        Ok(())
+    }
+
+    fn write_cbz(&self, zip: &mut ZipWriter<File>, comic_info: &str) -> Result<()> {
+        for page in self.pages()? {
+            zip.start_file(page.as_str(), stored())?;
+            zip.write_all(&std::fs::read(self.staging.join(&page))?)?;
+        }
+
+        zip.start_file(comicinfo::FILE_NAME, stored())?;
+        zip.write_all(comic_info.as_bytes())?;
+
+        Ok(())
+    }
+
+    fn write_epub(&self, zip: &mut ZipWriter<File>, comic_info: &str) -> Result<()> {
+        let info = comicinfo::ComicInfo::parse(comic_info).unwrap_or_default();
+        let title = match (info.series(), info.title()) {
+            (Some(series), Some(title)) => format!("{series} - {title}"),
+            (series, title) => series.or(title).unwrap_or_default().to_string(),
+        };
+        let pages = self.pages()?;
+
+        // mimetype has to be the first entry and uncompressed
+        zip.start_file("mimetype", stored())?;
+        zip.write_all(b"application/epub+zip")?;
+
+        zip.start_file("META-INF/container.xml", stored())?;
+        zip.write_all(
+            br#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#,
+        )?;
+
+        zip.start_file(comicinfo::FILE_NAME, stored())?;
+        zip.write_all(comic_info.as_bytes())?;
+
+        let mut manifest = String::new();
+        let mut spine = String::new();
+        for (index, page) in pages.iter().enumerate() {
+            // page names come from the url and may need escaping in an href, images are
+            // named by index like their xhtml documents instead
+            let image = match Path::new(page).extension() {
+                Some(extension) => format!("image{index}.{}", extension.to_string_lossy()),
+                None => format!("image{index}"),
+            };
+            let data = std::fs::read(self.staging.join(page))?;
+            zip.start_file(format!("OEBPS/images/{image}"), stored())?;
+            zip.write_all(&data)?;
+
+            zip.start_file(format!("OEBPS/pages/page{index}.xhtml"), stored())?;
+            write!(
+                zip,
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml">
+<head><title>{index}</title></head>
+<body><img src="../images/{image}" alt="{index}"/></body>
+</html>
+"#
+            )?;
+
+            let properties = if index == 0 {
+                r#" properties="cover-image""#
+            } else {
+                ""
+            };
+            manifest.push_str(&format!(
+                "    <item id=\"image{index}\" href=\"images/{image}\" media-type=\"{}\"{properties}/>\n",
+                media_type(page)
+            ));
+            manifest.push_str(&format!(
+                "    <item id=\"page{index}\" href=\"pages/page{index}.xhtml\" media-type=\"application/xhtml+xml\"/>\n"
+            ));
+            spine.push_str(&format!("    <itemref idref=\"page{index}\"/>\n"));
+        }
+
+        zip.start_file("OEBPS/nav.xhtml", stored())?;
+        write!(
+            zip,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head><title>{title}</title></head>
+<body><nav epub:type="toc"><ol><li><a href="pages/page0.xhtml">{title}</a></li></ol></nav></body>
+</html>
+"#,
+            title = escape(&title)
+        )?;
+
+        let creators: String = info
+            .authors()
+            .unwrap_or_default()
+            .iter()
+            .map(|author| format!("    <dc:creator>{}</dc:creator>\n", escape(author)))
+            .collect();
+        zip.start_file("OEBPS/content.opf", stored())?;
+        write!(
+            zip,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="id">{identifier}</dc:identifier>
+    <dc:title>{title}</dc:title>
+    <dc:language>und</dc:language>
+{creators}    <meta property="dcterms:modified">{modified}</meta>
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+{manifest}  </manifest>
+  <spine>
+{spine}  </spine>
+</package>
+"#,
+            identifier = escape(&self.path.display().to_string()),
+            title = escape(&title),
+            modified = Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
+        )?;
+
+        Ok(())
+    }
+}
+
+fn media_type(name: &str) -> &'static str {
+    match Path::new(name)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .as_deref()
+    {
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("bmp") => "image/bmp",
+        Some("jxl") => "image/jxl",
+        _ => "image/jpeg",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::application::worker::downloads::verify;
+
+    const PNG: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0];
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("tanoshi-output-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn test_comic_info(page_count: usize) -> String {
+        comicinfo::build_xml(&[
+            ("Title", "Title".to_string()),
+            ("Series", "Manga".to_string()),
+            ("PageCount", page_count.to_string()),
+        ])
+    }
+
+    #[test]
+    fn test_with_extension() {
+        assert_eq!(
+            with_extension(Path::new("Manga/1.5 - Title"), "cbz"),
+            Path::new("Manga/1.5 - Title.cbz")
+        );
+    }
+
+    #[test]
+    fn test_resume_staged_pages() {
+        let dir = temp_dir("resume");
+        let path = dir.join("1 - Title");
+
+        let mut output = ChapterOutput::open(DownloadFormat::Cbz, &path).unwrap();
+        assert!(!output.contains("000"));
+        output.write_page("000", "png", PNG).unwrap();
+        // a write interrupted before its rename
+        std::fs::write(output.staging.join("001.png.part"), PNG).unwrap();
+        assert!(output.contains("000"));
+
+        let output = ChapterOutput::open(DownloadFormat::Cbz, &path).unwrap();
+        assert!(output.contains("000"));
+        assert!(!output.contains("001"));
+        assert_eq!(output.pages().unwrap(), vec!["000.png"]);
+        assert!(!output.path.exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_complete() {
+        for format in [
+            DownloadFormat::Cbz,
+            DownloadFormat::Folder,
+            DownloadFormat::Epub,
+        ] {
+            let dir = temp_dir(&format!("{format:?}"));
+            let path = dir.join("1 - Title");
+
+            let mut output = ChapterOutput::open(format, &path).unwrap();
+            let staging = output.staging.clone();
+            output.write_page("000", "png", PNG).unwrap();
+            // page names come from url with the `{filename}` template
+            output.write_page("001 #page 1%", "png", PNG).unwrap();
+
+            let completed = output.complete(&test_comic_info(2)).unwrap();
+            assert_eq!(completed, output_path(format, &path));
+            assert!(!staging.exists());
+            verify::verify_chapter(&completed).unwrap();
+
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_complete_missing_pages() {
+        let dir = temp_dir("missing");
+        let path = dir.join("1 - Title");
+
+        let mut output = ChapterOutput::open(DownloadFormat::Cbz, &path).unwrap();
+        output.write_page("000", "png", PNG).unwrap();
+        let completed = output.complete(&test_comic_info(2)).unwrap();
+        assert!(verify::verify_chapter(&completed).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_complete_folder_replaces_existing() {
+        let dir = temp_dir("replace");
+        let path = dir.join("1 - Title");
+        std::fs::create_dir_all(&path).unwrap();
+        std::fs::write(path.join("old.png"), PNG).unwrap();
+
+        let mut output = ChapterOutput::open(DownloadFormat::Folder, &path).unwrap();
+        output.write_page("000", "png", PNG).unwrap();
+        let completed = output.complete(&test_comic_info(1)).unwrap();
+
+        assert_eq!(completed, path);
+        assert!(!path.join("old.png").exists());
+        assert!(path.join("000.png").exists());
+        assert!(path.join(comicinfo::FILE_NAME).exists());
+        verify::verify_chapter(&path).unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
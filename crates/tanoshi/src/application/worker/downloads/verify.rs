@@ -0,0 +1,151 @@
+use std::{io::Read, path::Path};
+
+use anyhow::{anyhow, bail, Context, Result};
+use mime_guess::mime;
+
+use crate::infrastructure::local::comicinfo::{self, ComicInfo};
+
+/// Extension of an image by its header
+pub fn image_extension(data: &[u8]) -> Option<&'static str> {
+    let extension = match data {
+        [0xFF, 0xD8, 0xFF, ..] => "jpg",
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => "png",
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => "gif",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "webp",
+        [_, _, _, _, b'f', b't', b'y', b'p', b'a', b'v', b'i', b'f' | b's', ..] => "avif",
+        [b'B', b'M', ..] => "bmp",
+        [0xFF, 0x0A, ..] | [0, 0, 0, 0x0C, b'J', b'X', b'L', b' ', ..] => "jxl",
+        _ => return None,
+    };
+
+    Some(extension)
+}
+
+/// A page has content and an image header, returns extension of the image.
+/// Some servers send images as `application/octet-stream` so only other types are refused.
+pub fn validate_page(content_type: Option<&str>, data: &[u8]) -> Result<&'static str> {
+    if data.is_empty() {
+        bail!("page is empty");
+    }
+
+    if let Some(content_type) = content_type.map(|content_type| content_type.to_lowercase()) {
+        if !content_type.starts_with("image/")
+            && !content_type.starts_with("application/octet-stream")
+            && !content_type.starts_with("binary/octet-stream")
+        {
+            bail!("page has content type {content_type}");
+        }
+    }
+
+    image_extension(data).ok_or_else(|| anyhow!("page is not a supported image"))
+}
+
+fn is_page(name: &str) -> bool {
+    mime_guess::from_path(name)
+        .first()
+        .map(|m| m.type_() == mime::IMAGE)
+        .unwrap_or(false)
+}
+
+fn check_page_count(comic_info: Option<ComicInfo>, pages: usize) -> Result<()> {
+    if pages == 0 {
+        bail!("chapter has no pages");
+    }
+
+    match comic_info.and_then(|info| info.page_count()) {
+        Some(page_count) if page_count > pages => {
+            bail!("chapter has {pages} of {page_count} pages")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Check a downloaded chapter, every page is read so truncated or corrupted archives fail
+pub fn verify_chapter(path: &Path) -> Result<()> {
+    let mut pages = 0;
+    let mut comic_info = None;
+
+    if path.is_dir() {
+        for entry in path.read_dir()?.filter_map(Result::ok) {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name == comicinfo::FILE_NAME {
+                comic_info = ComicInfo::read(path);
+            } else if is_page(&name) {
+                let data = std::fs::read(entry.path())?;
+                validate_page(None, &data).with_context(|| format!("invalid page {name}"))?;
+                pages += 1;
+            }
+        }
+    } else if path.is_file() {
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(path)?)?;
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index)?;
+            let name = file.name().to_string();
+            if file.is_dir() || !(name == comicinfo::FILE_NAME || is_page(&name)) {
+                continue;
+            }
+
+            // crc is checked once the entry is read to the end
+            let mut data = vec![];
+            file.read_to_end(&mut data)
+                .with_context(|| format!("failed to read {name}"))?;
+
+            if name == comicinfo::FILE_NAME {
+                comic_info = ComicInfo::parse(&String::from_utf8_lossy(&data));
+            } else {
+                validate_page(None, &data).with_context(|| format!("invalid page {name}"))?;
+                pages += 1;
+            }
+        }
+    } else {
+        bail!("{} not found", path.display());
+    }
+
+    check_page_count(comic_info, pages)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_validate_page() {
+        let png = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0];
+
+        assert_eq!(validate_page(Some("image/png"), &png).unwrap(), "png");
+        assert_eq!(
+            validate_page(Some("application/octet-stream"), &png).unwrap(),
+            "png"
+        );
+        assert_eq!(
+            validate_page(None, &[0xFF, 0xD8, 0xFF, 0xE0]).unwrap(),
+            "jpg"
+        );
+        assert_eq!(
+            validate_page(None, b"RIFF\0\0\0\0WEBPVP8 ").unwrap(),
+            "webp"
+        );
+        assert!(validate_page(Some("text/html"), &png).is_err());
+        assert!(validate_page(Some("image/png"), b"<html></html>").is_err());
+        assert!(validate_page(Some("image/png"), &[]).is_err());
+    }
+
+    #[test]
+    fn test_verify_chapter() {
+        assert!(verify_chapter(Path::new(
+            "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver"
+        ))
+        .is_ok());
+        assert!(verify_chapter(Path::new("../../test/data/manga")).is_err());
+        assert!(verify_chapter(Path::new("../../test/data/manga/missing.cbz")).is_err());
+    }
+
+    #[test]
+    fn test_check_page_count() {
+        let info = ComicInfo::parse("<ComicInfo><PageCount>2</PageCount></ComicInfo>");
+
+        assert!(check_page_count(None, 1).is_ok());
+        assert!(check_page_count(None, 0).is_err());
+        assert!(check_page_count(info, 1).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
+
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::{task::JoinHandle, time::Instant};
+
+use crate::infrastructure::local::index::LocalIndex;
+
+use super::updates::{ChapterUpdateCommand, ChapterUpdateCommandSender};
+
+// changes are collected until nothing happens for this long, copying a chapter emits many events
+const DEBOUNCE: Duration = Duration::from_secs(5);
+// changes are flushed at least this often while events keep arriving
+const MAX_DEBOUNCE: Duration = Duration::from_secs(60);
+
+fn watch(
+    source_id: i64,
+    index: &LocalIndex,
+    tx: tokio::sync::mpsc::UnboundedSender<(i64, PathBuf)>,
+) -> Result<RecommendedWatcher, notify::Error> {
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| match res {
+        Ok(event) if !event.kind.is_access() => {
+            for path in event.paths {
+                let _ = tx.send((source_id, path));
+            }
+        }
+        Ok(_) => {}
+        Err(e) => error!("error watching local source {source_id}: {e}"),
+    })?;
+    watcher.watch(index.root(), RecursiveMode::Recursive)?;
+
+    Ok(watcher)
+}
+
+async fn refresh(
+    source_id: i64,
+    index: Arc<LocalIndex>,
+    paths: Vec<PathBuf>,
+    command_tx: &ChapterUpdateCommandSender,
+) -> Result<(), anyhow::Error> {
+    let changed = paths.clone();
+    tokio::task::spawn_blocking(move || index.update(&changed)).await??;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    command_tx
+        .send_async(ChapterUpdateCommand::Paths(source_id, paths, tx))
+        .await?;
+    rx.await?
+}
+
+/// Watch folders of local sources, rebuild their index and check chapter updates
+/// of affected manga when files change
+pub fn start(
+    libraries: Vec<(i64, Arc<LocalIndex>)>,
+    command_tx: ChapterUpdateCommandSender,
+) -> JoinHandle<()> {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let mut watchers = vec![];
+    for (source_id, index) in libraries.iter() {
+        match watch(*source_id, index, tx.clone()) {
+            Ok(watcher) => watchers.push(watcher),
+            Err(e) => error!("failed to watch {}: {e}", index.root().display()),
+        }
+    }
+
+    let indexes: HashMap<i64, Arc<LocalIndex>> = libraries.into_iter().collect();
+
+    tokio::spawn(async move {
+        // watchers stop when dropped
+        let _watchers = watchers;
+
+        while let Some((source_id, path)) = rx.recv().await {
+            let mut changes: HashMap<i64, Vec<PathBuf>> = HashMap::new();
+            changes.entry(source_id).or_default().push(path);
+
+            let deadline = Instant::now() + MAX_DEBOUNCE;
+            loop {
+                let wait = DEBOUNCE.min(deadline.saturating_duration_since(Instant::now()));
+                match tokio::time::timeout(wait, rx.recv()).await {
+                    Ok(Some((source_id, path))) => changes.entry(source_id).or_default().push(path),
+                    _ => break,
+                }
+            }
+
+            for (source_id, mut paths) in changes {
+                let index = match indexes.get(&source_id) {
+                    Some(index) => index.clone(),
+                    None => continue,
+                };
+
+                paths.sort();
+                paths.dedup();
+                debug!("{} files changed in local source {source_id}", paths.len());
+
+                if let Err(e) = refresh(source_id, index, paths, &command_tx).await {
+                    error!("failed to refresh local source {source_id}: {e}");
+                }
+            }
+        }
+    })
+}
@@ -10,7 +10,7 @@ use rayon::prelude::*;
 use serde::Deserialize;
 
 use tanoshi_lib::prelude::Version;
-use tanoshi_vm::extension::ExtensionManager;
+use tanoshi_vm::extension::{ExtensionManager, Repository};
 
 use crate::{
     domain::{
@@ -41,6 +41,12 @@ pub enum ChapterUpdateCommand {
     All(tokio::sync::oneshot::Sender<Result<(), anyhow::Error>>),
     Manga(i64, tokio::sync::oneshot::Sender<Result<(), anyhow::Error>>),
     Library(i64, tokio::sync::oneshot::Sender<Result<(), anyhow::Error>>),
+    /// Manga of a source with changes under its path, e.g. files of a local source
+    Paths(
+        i64,
+        Vec<PathBuf>,
+        tokio::sync::oneshot::Sender<Result<(), anyhow::Error>>,
+    ),
 }
 
 pub type ChapterUpdateCommandReceiver = flume::Receiver<ChapterUpdateCommand>;
@@ -67,7 +73,7 @@ where
     chapter_repo: C,
     extensions: ExtensionManager,
     notifier: Notification<UserRepositoryImpl>,
-    extension_repository: String,
+    extension_repositories: Vec<Repository>,
     cache_path: PathBuf,
     broadcast_tx: ChapterUpdateSender,
     command_rx: ChapterUpdateCommandReceiver,
@@ -84,7 +90,7 @@ where
         chapter_repo: C,
         extensions: ExtensionManager,
         notifier: Notification<UserRepositoryImpl>,
-        extension_repository: String,
+        extension_repositories: Vec<Repository>,
         broadcast_tx: ChapterUpdateSender,
         cache_path: P,
     ) -> (Self, ChapterUpdateCommandSender) {
@@ -106,7 +112,7 @@ where
                 chapter_repo,
                 extensions,
                 notifier,
-                extension_repository,
+                extension_repositories,
                 cache_path: PathBuf::new().join(cache_path),
                 broadcast_tx,
                 command_rx,
@@ -184,6 +190,40 @@ where
         });
     }
 
+    fn start_chapter_update_queue_by_paths(
+        &self,
+        tx: tokio::sync::mpsc::Sender<Result<Manga, LibraryRepositoryError>>,
+        source_id: i64,
+        paths: Vec<PathBuf>,
+    ) {
+        let library_repo = self.library_repo.clone();
+
+        let rt = tokio::runtime::Handle::current();
+        std::thread::spawn(move || {
+            rt.block_on(async move {
+                let mut manga_stream = library_repo.get_manga_from_all_users_library_stream().await;
+
+                while let Some(manga) = manga_stream.next().await {
+                    let changed = match &manga {
+                        Ok(manga) => {
+                            manga.source_id == source_id
+                                && paths.iter().any(|path| path.starts_with(&manga.path))
+                        }
+                        Err(_) => true,
+                    };
+                    if !changed {
+                        continue;
+                    }
+
+                    if let Err(e) = tx.send(manga).await {
+                        error!("error send update: {e:?}");
+                        break;
+                    }
+                }
+            });
+        });
+    }
+
     async fn check_chapter_update(
         &self,
         mut rx: tokio::sync::mpsc::Receiver<Result<Manga, LibraryRepositoryError>>,
@@ -191,6 +231,14 @@ where
         while let Some(Ok(manga)) = rx.recv().await {
             debug!("Checking updates: {}", manga.title);
 
+            if self.extensions.is_suspended(manga.source_id) {
+                debug!(
+                    "source {} is suspended, skip {}",
+                    manga.source_id, manga.title
+                );
+                continue;
+            }
+
             let chapters: Vec<Chapter> = match self
                 .extensions
                 .get_chapters(manga.source_id, manga.path.clone())
@@ -286,17 +334,14 @@ where
     }
 
     async fn check_extension_update(&self) -> Result<(), anyhow::Error> {
-        let url = format!("{}/index.json", self.extension_repository);
-
         let available_sources_map = self
-            .client
-            .get(&url)
-            .send()
-            .await?
-            .json::<Vec<SourceInfo>>()
-            .await?
-            .into_par_iter()
-            .map(|source| (source.id, source))
+            .extensions
+            .fetch_indexes(&self.extension_repositories, |source: &SourceInfo| {
+                source.id
+            })
+            .await
+            .into_iter()
+            .map(|(_, source)| (source.id, source))
             .collect::<HashMap<i64, SourceInfo>>();
 
         let installed_sources = self.extensions.list().await?;
@@ -305,7 +350,7 @@ where
             if available_sources_map
                 .get(&source.id)
                 .and_then(|index| Version::from_str(&index.version).ok())
-                .map(|v| v > Version::from_str(source.version).unwrap_or_default())
+                .map(|v| v > Version::from_str(&source.version).unwrap_or_default())
                 .unwrap_or(false)
             {
                 let message = format!("{} extension update available", source.name);
@@ -425,6 +470,13 @@ where
                             if let Err(_) = tx.send(res) {
                                 info!("failed to send chapter update result");
                             }
+                        },
+                        ChapterUpdateCommand::Paths(source_id, paths, tx) => {
+                            self.start_chapter_update_queue_by_paths(manga_tx, source_id, paths);
+                            let res = self.check_chapter_update(manga_rx).await;
+                            if let Err(_) = tx.send(res) {
+                                info!("failed to send chapter update result");
+                            }
                         }
                     }
                 }
@@ -472,7 +524,7 @@ pub fn start<C, L, P>(
     chapter_repo: C,
     extensions: ExtensionManager,
     notifier: Notification<UserRepositoryImpl>,
-    extension_repository: String,
+    extension_repositories: Vec<Repository>,
     cache_path: P,
 ) -> (
     ChapterUpdateReceiver,
@@ -491,7 +543,7 @@ where
         chapter_repo,
         extensions,
         notifier,
-        extension_repository,
+        extension_repositories,
         broadcast_tx,
         cache_path,
     );
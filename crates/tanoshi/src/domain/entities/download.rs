@@ -1,4 +1,4 @@
-use chrono::NaiveDateTime;
+use chrono::{NaiveDateTime, NaiveTime};
 
 #[derive(Debug, Clone)]
 pub struct DownloadQueue {
@@ -26,6 +26,9 @@ pub struct DownloadQueueEntry {
     pub downloaded: i64,
     pub total: i64,
     pub priority: i64,
+    /// Retries of a page ran out, the chapter is skipped until retried
+    pub failed: bool,
+    pub error: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -41,3 +44,123 @@ pub struct DownloadedChapter {
     pub date_added: NaiveDateTime,
     pub downloaded_path: Option<String>,
 }
+
+/// A chapter with no `downloaded_path` that is not in download queue
+#[derive(Debug, Clone)]
+pub struct UndownloadedChapter {
+    pub chapter_id: i64,
+    pub source_id: i64,
+    pub manga_id: i64,
+    pub manga_title: String,
+    pub chapter_title: String,
+    pub chapter_number: f64,
+    pub chapter_scanlator: String,
+}
+
+/// Daily window in local time in which chapters are downloaded, may cross midnight
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DownloadSchedule {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl DownloadSchedule {
+    /// Whether `time` is inside the window, which wraps around midnight if `end` is before
+    /// `start`. A window starting and ending at the same time covers the whole day.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start == self.end {
+            true
+        } else if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DownloadSettings {
+    pub paused: bool,
+    pub schedule: Option<DownloadSchedule>,
+}
+
+impl DownloadSettings {
+    /// Whether the worker should download at `time`
+    pub fn is_active(&self, time: NaiveTime) -> bool {
+        !self.paused
+            && self
+                .schedule
+                .map(|schedule| schedule.contains(time))
+                .unwrap_or(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn time(hour: u32, min: u32) -> NaiveTime {
+        NaiveTime::from_hms(hour, min, 0)
+    }
+
+    #[test]
+    fn test_schedule_contains() {
+        let night = DownloadSchedule {
+            start: time(1, 0),
+            end: time(7, 0),
+        };
+        assert!(night.contains(time(1, 0)));
+        assert!(night.contains(time(6, 59)));
+        assert!(!night.contains(time(7, 0)));
+        assert!(!night.contains(time(0, 59)));
+        assert!(!night.contains(time(12, 0)));
+
+        let wrapped = DownloadSchedule {
+            start: time(22, 0),
+            end: time(6, 0),
+        };
+        assert!(wrapped.contains(time(22, 0)));
+        assert!(wrapped.contains(time(23, 59)));
+        assert!(wrapped.contains(time(0, 0)));
+        assert!(wrapped.contains(time(5, 59)));
+        assert!(!wrapped.contains(time(6, 0)));
+        assert!(!wrapped.contains(time(21, 59)));
+
+        let all_day = DownloadSchedule {
+            start: time(3, 0),
+            end: time(3, 0),
+        };
+        assert!(all_day.contains(time(3, 0)));
+        assert!(all_day.contains(time(2, 59)));
+        assert!(all_day.contains(time(15, 0)));
+    }
+
+    #[test]
+    fn test_settings_is_active() {
+        let schedule = Some(DownloadSchedule {
+            start: time(1, 0),
+            end: time(7, 0),
+        });
+
+        assert!(DownloadSettings::default().is_active(time(12, 0)));
+
+        let paused = DownloadSettings {
+            paused: true,
+            schedule: None,
+        };
+        assert!(!paused.is_active(time(12, 0)));
+
+        let scheduled = DownloadSettings {
+            paused: false,
+            schedule,
+        };
+        assert!(scheduled.is_active(time(2, 0)));
+        assert!(!scheduled.is_active(time(12, 0)));
+
+        let paused_scheduled = DownloadSettings {
+            paused: true,
+            schedule,
+        };
+        assert!(!paused_scheduled.is_active(time(2, 0)));
+    }
+}
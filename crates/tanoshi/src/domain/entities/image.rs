@@ -16,6 +16,8 @@ pub enum ImageUri {
     Remote(String),
     File(String),
     Archive(String, String),
+    /// Pdf file and 1-based page number
+    Pdf(String, u32),
 }
 
 impl TryFrom<&str> for ImageUri {
@@ -29,14 +31,18 @@ impl TryFrom<&str> for ImageUri {
             if path.is_file() {
                 Self::File(uri.to_string())
             } else {
-                let regex = format!(r#"\.({})[\/|\\]"#, SUPPORTED_FILES.iter().join("|"));
+                let regex = format!(r#"(?i)\.({})[\/|\\]"#, SUPPORTED_FILES.iter().join("|"));
                 let re = Regex::new(&regex)?;
 
                 if let Some(matches) = re.find(uri).ok().flatten() {
                     let archive = uri[0..matches.end() - 1].to_owned();
                     let filename = uri[matches.end()..uri.len()].to_owned();
 
-                    Self::Archive(archive, filename)
+                    if archive.to_lowercase().ends_with(".pdf") {
+                        Self::Pdf(archive, filename.parse()?)
+                    } else {
+                        Self::Archive(archive, filename)
+                    }
                 } else {
                     return Err(anyhow!("invalid file uri"));
                 }
@@ -91,6 +97,7 @@ impl ToString for ImageUri {
             ImageUri::Remote(url) => url.to_owned(),
             ImageUri::File(path) => path.to_owned(),
             ImageUri::Archive(archive, filename) => format!("{archive}/{filename}"),
+            ImageUri::Pdf(pdf, page) => format!("{pdf}/{page}"),
         }
     }
 }
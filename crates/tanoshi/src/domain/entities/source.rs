@@ -7,6 +7,8 @@ pub struct Source {
     pub lib_version: String,
     pub icon: String,
     pub has_update: bool,
+    /// Name of the repository the source is installed from or available in
+    pub repository: Option<String>,
 }
 
 impl From<tanoshi_lib::models::SourceInfo> for Source {
@@ -20,6 +22,7 @@ impl From<tanoshi_lib::models::SourceInfo> for Source {
             lib_version: "".to_string(),
             icon: s.icon.to_string(),
             has_update: false,
+            repository: None,
         }
     }
 }
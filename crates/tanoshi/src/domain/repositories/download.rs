@@ -2,7 +2,10 @@ use async_trait::async_trait;
 
 use thiserror::Error;
 
-use crate::domain::entities::download::{DownloadQueue, DownloadQueueEntry, DownloadedChapter};
+use crate::domain::entities::download::{
+    DownloadQueue, DownloadQueueEntry, DownloadSchedule, DownloadSettings, DownloadedChapter,
+    UndownloadedChapter,
+};
 
 #[derive(Debug, Error)]
 pub enum DownloadRepositoryError {
@@ -38,6 +41,10 @@ pub trait DownloadRepository: Send + Sync {
         before_id: i64,
     ) -> Result<Vec<DownloadedChapter>, DownloadRepositoryError>;
 
+    async fn get_all_downloaded_chapters(
+        &self,
+    ) -> Result<Vec<DownloadedChapter>, DownloadRepositoryError>;
+
     async fn get_chapter_downloaded_path(
         &self,
         chapter_id: i64,
@@ -49,6 +56,10 @@ pub trait DownloadRepository: Send + Sync {
         path: Option<String>,
     ) -> Result<(), DownloadRepositoryError>;
 
+    async fn get_undownloaded_chapters(
+        &self,
+    ) -> Result<Vec<UndownloadedChapter>, DownloadRepositoryError>;
+
     async fn insert_download_queue(
         &self,
         items: &[DownloadQueue],
@@ -58,6 +69,11 @@ pub trait DownloadRepository: Send + Sync {
         &self,
     ) -> Result<Option<DownloadQueue>, DownloadRepositoryError>;
 
+    async fn get_chapter_download_queue(
+        &self,
+        chapter_id: i64,
+    ) -> Result<Vec<DownloadQueue>, DownloadRepositoryError>;
+
     async fn get_single_chapter_download_status(
         &self,
         chapter_id: i64,
@@ -68,6 +84,17 @@ pub trait DownloadRepository: Send + Sync {
         id: i64,
     ) -> Result<(), DownloadRepositoryError>;
 
+    async fn mark_chapter_download_queue_as_failed(
+        &self,
+        chapter_id: i64,
+        error: &str,
+    ) -> Result<(), DownloadRepositoryError>;
+
+    async fn retry_failed_download_queue(
+        &self,
+        chapter_id: i64,
+    ) -> Result<(), DownloadRepositoryError>;
+
     async fn get_download_queue_last_priority(
         &self,
     ) -> Result<Option<i64>, DownloadRepositoryError>;
@@ -89,4 +116,13 @@ pub trait DownloadRepository: Send + Sync {
         chapter_id: i64,
         priority: i64,
     ) -> Result<(), DownloadRepositoryError>;
+
+    async fn get_download_settings(&self) -> Result<DownloadSettings, DownloadRepositoryError>;
+
+    async fn update_download_paused(&self, paused: bool) -> Result<(), DownloadRepositoryError>;
+
+    async fn update_download_schedule(
+        &self,
+        schedule: Option<DownloadSchedule>,
+    ) -> Result<(), DownloadRepositoryError>;
 }
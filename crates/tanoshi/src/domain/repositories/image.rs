@@ -31,4 +31,11 @@ pub trait ImageRepository: Send + Sync {
     ) -> Result<Image, ImageRepositoryError>
     where
         P: AsRef<Path> + std::marker::Send;
+    async fn fetch_image_from_pdf<P>(
+        &self,
+        pdf: P,
+        page: u32,
+    ) -> Result<Image, ImageRepositoryError>
+    where
+        P: AsRef<Path> + std::marker::Send;
 }
@@ -1,5 +1,6 @@
 use async_trait::async_trait;
 
+use tanoshi_vm::extension::Repository;
 use thiserror::Error;
 
 use crate::domain::entities::source::Source;
@@ -24,14 +25,24 @@ pub trait SourceRepository: Send + Sync {
 
     async fn available_sources(
         &self,
-        repo_url: &str,
+        repositories: &[Repository],
         filter_installed: bool,
     ) -> Result<Vec<Source>, SourceRepositoryError>;
     async fn get_source_by_id(&self, id: i64) -> Result<Source, SourceRepositoryError>;
 
-    async fn install_source(&self, repo_url: &str, id: i64) -> Result<(), SourceRepositoryError>;
+    async fn install_source(
+        &self,
+        repositories: &[Repository],
+        id: i64,
+    ) -> Result<(), SourceRepositoryError>;
+
+    async fn update_source(
+        &self,
+        repositories: &[Repository],
+        id: i64,
+    ) -> Result<(), SourceRepositoryError>;
 
-    async fn update_source(&self, repo_url: &str, id: i64) -> Result<(), SourceRepositoryError>;
+    async fn rollback_source(&self, id: i64, version: &str) -> Result<(), SourceRepositoryError>;
 
     async fn uninstall_source(&self, id: i64) -> Result<(), SourceRepositoryError>;
 }
@@ -98,11 +98,19 @@ where
         path: &str,
         downloaded_path: &Option<String>,
     ) -> Result<Vec<String>, ChapterError> {
-        let pages = if let Some(downloaded_path) =
-            downloaded_path.as_ref().map(|p| PathBuf::new().join(p))
+        let pages = if let Some(downloaded_path) = downloaded_path
+            .as_ref()
+            .map(|p| PathBuf::new().join(p))
+            // read from source if the download was removed
+            .filter(|p| p.exists())
         {
             tokio::task::spawn_blocking(move || {
-                local::get_pages_from_archive(downloaded_path.as_path())
+                let filter = local::files::PageFilter::default();
+                if downloaded_path.is_dir() {
+                    local::get_pages_from_dir(&downloaded_path, &filter)
+                } else {
+                    local::get_pages_from_archive(&downloaded_path, &filter)
+                }
             })
             .await??
         } else {
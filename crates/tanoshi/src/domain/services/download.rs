@@ -1,9 +1,11 @@
-use std::path::{Path, PathBuf};
+use std::path::Path;
+
+use chrono::Local;
 
 use crate::{
-    application::worker::downloads::{Command as DownloadCommand, DownloadSender},
+    application::worker::downloads::{output, Command as DownloadCommand, DownloadSender},
     domain::{
-        entities::download::{DownloadQueueEntry, DownloadedChapter},
+        entities::download::{DownloadQueueEntry, DownloadSchedule, DownloadedChapter},
         repositories::download::{DownloadRepository, DownloadRepositoryError},
     },
 };
@@ -75,32 +77,56 @@ where
         Ok(chapters)
     }
 
-    pub fn get_download_status<P: AsRef<Path>>(&self, download_path: P) -> bool {
-        !PathBuf::new().join(download_path).join(".pause").exists()
+    /// Whether chapters are downloaded right now, false if paused or outside of schedule
+    pub async fn get_download_status(&self) -> Result<bool, DownloadError> {
+        let settings = self.repo.get_download_settings().await?;
+
+        Ok(settings.is_active(Local::now().time()))
     }
 
-    pub async fn change_download_status<P: AsRef<Path>>(
-        &self,
-        download_path: P,
-        status: bool,
-    ) -> Result<(), DownloadError> {
-        let pause_path = PathBuf::new().join(download_path).join(".pause");
+    /// Whether downloads are paused by user, a schedule doesn't pause downloads
+    pub async fn get_download_paused(&self) -> Result<bool, DownloadError> {
+        let settings = self.repo.get_download_settings().await?;
 
-        if status {
-            let _ = tokio::fs::remove_file(pause_path).await;
+        Ok(settings.paused)
+    }
+
+    pub async fn get_download_schedule(&self) -> Result<Option<DownloadSchedule>, DownloadError> {
+        let settings = self.repo.get_download_settings().await?;
 
+        Ok(settings.schedule)
+    }
+
+    pub async fn change_download_status(&self, status: bool) -> Result<(), DownloadError> {
+        self.repo.update_download_paused(!status).await?;
+
+        if status {
             self.download_sender
                 .send(DownloadCommand::Download)
                 .map_err(|_| {
                     DownloadError::OtherError(anyhow::anyhow!("failed to send download command"))
                 })?;
-        } else {
-            let _ = tokio::fs::write(pause_path, b"").await;
         }
 
         Ok(())
     }
 
+    /// Only download inside `schedule`, download any time if `None`
+    pub async fn change_download_schedule(
+        &self,
+        schedule: Option<DownloadSchedule>,
+    ) -> Result<(), DownloadError> {
+        self.repo.update_download_schedule(schedule).await?;
+
+        self.download_sender
+            .send(DownloadCommand::Download)
+            .map_err(|_| {
+                DownloadError::OtherError(anyhow::anyhow!("failed to send download command"))
+            })?;
+
+        Ok(())
+    }
+
     pub async fn get_download_queue(&self) -> Result<Vec<DownloadQueueEntry>, DownloadError> {
         let queue = self.repo.get_download_queue().await?;
 
@@ -137,6 +163,44 @@ where
         Ok(())
     }
 
+    /// Clear the failed status of chapters so they are downloaded again
+    pub async fn retry_failed_downloads(&self, chapter_ids: Vec<i64>) -> Result<(), DownloadError> {
+        for chapter_id in chapter_ids {
+            self.repo.retry_failed_download_queue(chapter_id).await?;
+        }
+
+        self.download_sender
+            .send(DownloadCommand::Download)
+            .map_err(|_| {
+                DownloadError::OtherError(anyhow::anyhow!("failed to send download command"))
+            })?;
+
+        Ok(())
+    }
+
+    /// Remove chapters from download queue if they failed, returns how many were removed
+    pub async fn discard_failed_downloads(
+        &self,
+        chapter_ids: Vec<i64>,
+    ) -> Result<usize, DownloadError> {
+        let failed: Vec<i64> = self
+            .repo
+            .get_download_queue()
+            .await?
+            .into_iter()
+            .filter(|entry| entry.failed && chapter_ids.contains(&entry.chapter_id))
+            .map(|entry| entry.chapter_id)
+            .collect();
+
+        for chapter_id in failed.iter() {
+            self.repo
+                .delete_download_queue_by_chapter_id(*chapter_id)
+                .await?;
+        }
+
+        Ok(failed.len())
+    }
+
     pub async fn remove_chapters_from_queue(
         &self,
         chapter_ids: Vec<i64>,
@@ -156,7 +220,13 @@ where
     ) -> Result<(), DownloadError> {
         for chapter_id in chapter_ids {
             if let Ok(downloaded_path) = self.repo.get_chapter_downloaded_path(chapter_id).await {
-                if let Err(e) = tokio::fs::remove_file(&downloaded_path).await {
+                let res = tokio::task::spawn_blocking(move || {
+                    output::remove_output(Path::new(&downloaded_path))
+                })
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|res| res);
+                if let Err(e) = res {
                     error!("error removing file: {e}");
                 }
             }
@@ -168,4 +238,16 @@ where
 
         Ok(())
     }
+
+    /// Verify every downloaded chapter in download worker, broken or incomplete ones are
+    /// queued to download again
+    pub async fn scan_downloads(&self) -> Result<(), DownloadError> {
+        self.download_sender
+            .send(DownloadCommand::ScanDownloads)
+            .map_err(|_| {
+                DownloadError::OtherError(anyhow::anyhow!("failed to send scan command"))
+            })?;
+
+        Ok(())
+    }
 }
@@ -67,6 +67,7 @@ where
                     .fetch_image_from_archive(&archive, &filename)
                     .await?
             }
+            ImageUri::Pdf(pdf, page) => self.repo.fetch_image_from_pdf(&pdf, page).await?,
         };
 
         Ok(image)
@@ -1,11 +1,16 @@
+use std::path::Path;
+
 use anyhow::anyhow;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use tanoshi_vm::prelude::ExtensionManager;
 use thiserror::Error;
 
-use crate::domain::{
-    entities::manga::{InputList, Manga},
-    repositories::manga::{MangaRepository, MangaRepositoryError},
+use crate::{
+    domain::{
+        entities::manga::{InputList, Manga},
+        repositories::manga::{MangaRepository, MangaRepositoryError},
+    },
+    infrastructure::local::{self, LocalMangaInfo},
 };
 
 #[derive(Debug, Error)]
@@ -125,4 +130,27 @@ where
 
         Ok(manga)
     }
+
+    /// Write metadata of a manga from a local source to its files, then refresh it from there
+    pub async fn update_local_manga(
+        &self,
+        id: i64,
+        info: LocalMangaInfo,
+    ) -> Result<Manga, MangaError> {
+        let manga = self.repo.get_manga_by_id(id).await?;
+        // source id 10000 and above are local sources
+        if manga.source_id < 10000 {
+            return Err(anyhow!("manga {id} is not from a local source").into());
+        }
+
+        // url of a local source is its folder
+        let root = self.sources.get_source_info(manga.source_id)?.url;
+        tokio::task::spawn_blocking(move || {
+            local::write_manga_info(Path::new(&root), Path::new(&manga.path), info)
+        })
+        .await
+        .map_err(|e| anyhow!("{e}"))??;
+
+        self.fetch_manga_by_id(id, true).await
+    }
 }
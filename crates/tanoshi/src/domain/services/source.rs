@@ -6,6 +6,7 @@ use crate::domain::{
 };
 
 use tanoshi_lib::prelude::Version;
+use tanoshi_vm::extension::Repository;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -36,7 +37,7 @@ where
 
     pub async fn get_installed_sources(
         &self,
-        repo_url: &str,
+        repositories: &[Repository],
         check_update: bool,
     ) -> Result<Vec<Source>, SourceError> {
         let mut sources = self.repo.installed_sources().await?;
@@ -44,7 +45,7 @@ where
         if check_update {
             let available_sources: HashMap<i64, Source> = self
                 .repo
-                .available_sources(repo_url, false)
+                .available_sources(repositories, false)
                 .await?
                 .into_iter()
                 .map(|s| (s.id, s))
@@ -67,8 +68,11 @@ where
         Ok(sources)
     }
 
-    pub async fn get_available_sources(&self, repo_url: &str) -> Result<Vec<Source>, SourceError> {
-        let sources = self.repo.available_sources(repo_url, true).await?;
+    pub async fn get_available_sources(
+        &self,
+        repositories: &[Repository],
+    ) -> Result<Vec<Source>, SourceError> {
+        let sources = self.repo.available_sources(repositories, true).await?;
 
         Ok(sources)
     }
@@ -79,14 +83,28 @@ where
         Ok(source)
     }
 
-    pub async fn install_source(&self, repo_url: &str, id: i64) -> Result<(), SourceError> {
-        self.repo.install_source(repo_url, id).await?;
+    pub async fn install_source(
+        &self,
+        repositories: &[Repository],
+        id: i64,
+    ) -> Result<(), SourceError> {
+        self.repo.install_source(repositories, id).await?;
+
+        Ok(())
+    }
+
+    pub async fn update_source(
+        &self,
+        repositories: &[Repository],
+        id: i64,
+    ) -> Result<(), SourceError> {
+        self.repo.update_source(repositories, id).await?;
 
         Ok(())
     }
 
-    pub async fn update_source(&self, repo_url: &str, id: i64) -> Result<(), SourceError> {
-        self.repo.update_source(repo_url, id).await?;
+    pub async fn rollback_source(&self, id: i64, version: &str) -> Result<(), SourceError> {
+        self.repo.rollback_source(id, version).await?;
 
         Ok(())
     }
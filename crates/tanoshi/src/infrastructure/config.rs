@@ -2,7 +2,12 @@ use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
-use std::{iter, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    iter,
+    path::PathBuf,
+};
+use tanoshi_vm::extension::{Repository, Timeouts};
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct TelegramConfig {
@@ -32,12 +37,163 @@ pub struct AniListConfig {
     pub client_secret: String,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, async_graphql::Enum)]
+pub enum ReadingDirection {
+    LeftToRight,
+    RightToLeft,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct LocalFolder {
+    /// Source id, 10000 or above. Defaults to 10000 plus position in the list, set it to keep
+    /// library entries when folders are reordered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i64>,
     pub name: String,
     pub path: String,
+    #[serde(default)]
+    pub nsfw: bool,
+    /// Languages of the manga in this folder, all languages if empty
+    #[serde(default)]
+    pub languages: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reading_direction: Option<ReadingDirection>,
+    /// Glob patterns of chapter files to read, e.g. `*.cbz`
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Glob patterns of files and folders to skip
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Read files and folders whose name starts with `.`
+    #[serde(default)]
+    pub hidden: bool,
+    /// Glob patterns of images inside chapters that are not pages, e.g. `**/credits*`
+    #[serde(default)]
+    pub exclude_pages: Vec<String>,
+    /// Overrides `local_max_depth` for this folder
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<usize>,
+}
+
+/// Limits of a single source, unset values fall back to `DownloadLimits`
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct SourceDownloadLimits {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requests_per_second: Option<f64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct DownloadLimits {
+    /// Chapters downloaded at the same time across all sources
+    #[serde(default = "default_download_concurrency")]
+    pub concurrency: usize,
+    /// Chapters of a single source downloaded at the same time
+    #[serde(default = "default_download_concurrency_per_source")]
+    pub concurrency_per_source: usize,
+    /// Page requests per second to a single source, unlimited if 0
+    #[serde(default = "default_download_requests_per_second")]
+    pub requests_per_second: f64,
+    /// Overrides by source id
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub sources: HashMap<i64, SourceDownloadLimits>,
+    /// Attempts of a page before its chapter is marked as failed
+    #[serde(default = "default_download_max_attempts")]
+    pub max_attempts: u32,
+    /// Seconds before the first retry of a page, doubled on each retry
+    #[serde(default = "default_download_retry_delay")]
+    pub retry_delay: u64,
+}
+
+impl Default for DownloadLimits {
+    fn default() -> Self {
+        Self {
+            concurrency: default_download_concurrency(),
+            concurrency_per_source: default_download_concurrency_per_source(),
+            requests_per_second: default_download_requests_per_second(),
+            sources: HashMap::new(),
+            max_attempts: default_download_max_attempts(),
+            retry_delay: default_download_retry_delay(),
+        }
+    }
+}
+
+impl DownloadLimits {
+    pub fn source_concurrency(&self, source_id: i64) -> usize {
+        self.sources
+            .get(&source_id)
+            .and_then(|source| source.concurrency)
+            .unwrap_or(self.concurrency_per_source)
+    }
+
+    pub fn source_requests_per_second(&self, source_id: i64) -> f64 {
+        self.sources
+            .get(&source_id)
+            .and_then(|source| source.requests_per_second)
+            .unwrap_or(self.requests_per_second)
+    }
 }
 
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+pub enum DownloadFormat {
+    Cbz,
+    /// Plain folder of images
+    Folder,
+    Epub,
+}
+
+impl Default for DownloadFormat {
+    fn default() -> Self {
+        DownloadFormat::Cbz
+    }
+}
+
+/// How downloaded chapters are written, templates fill `{name}` placeholders and pad numbers
+/// with `{name:03}`
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct DownloadOutput {
+    #[serde(default)]
+    pub format: DownloadFormat,
+    /// Folder of a manga inside `download_path`, `/` separates folders.
+    /// Placeholders: `source`, `source_id`, `manga`, `manga_id`
+    #[serde(default = "default_download_directory")]
+    pub directory: String,
+    /// Archive or folder name of a chapter, without extension. Placeholders of `directory`
+    /// and `number`, `title`, `scanlator`, `chapter_id`
+    #[serde(default = "default_download_chapter")]
+    pub chapter: String,
+    /// Page name without extension. Placeholders: `rank` starting from 0, `filename` from url
+    #[serde(default = "default_download_page")]
+    pub page: String,
+}
+
+impl DownloadOutput {
+    /// How deep chapters are nested in `download_path`, used as `max_depth` of the downloads
+    /// local source
+    pub fn depth(&self) -> usize {
+        format!("{}/{}", self.directory, self.chapter)
+            .split('/')
+            .filter(|component| !component.trim().is_empty())
+            .count()
+            .max(1)
+    }
+}
+
+impl Default for DownloadOutput {
+    fn default() -> Self {
+        Self {
+            format: DownloadFormat::default(),
+            directory: default_download_directory(),
+            chapter: default_download_chapter(),
+            page: default_download_page(),
+        }
+    }
+}
+
+/// Source id of the download directory when `local_downloads` is enabled
+pub const DOWNLOADS_SOURCE_ID: i64 = 19999;
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum LocalFolders {
@@ -45,12 +201,50 @@ pub enum LocalFolders {
     Multiple(Vec<LocalFolder>),
 }
 
+impl LocalFolders {
+    /// Folders with their source id, a single folder is source 10000 named "Local"
+    pub fn folders(&self) -> Result<Vec<(i64, LocalFolder)>, anyhow::Error> {
+        let folders: Vec<(i64, LocalFolder)> = match self {
+            LocalFolders::Single(path) => vec![(
+                10000,
+                LocalFolder {
+                    name: "Local".to_string(),
+                    path: path.clone(),
+                    ..Default::default()
+                },
+            )],
+            LocalFolders::Multiple(folders) => folders
+                .iter()
+                .enumerate()
+                .map(|(index, folder)| (folder.id.unwrap_or(10000 + index as i64), folder.clone()))
+                .collect(),
+        };
+
+        let mut ids = HashSet::new();
+        for (id, folder) in folders.iter() {
+            if *id < 10000 {
+                anyhow::bail!("id of local folder {} must be 10000 or above", folder.name);
+            }
+            if !ids.insert(*id) {
+                anyhow::bail!("local folder {} has duplicate id {id}", folder.name);
+            }
+        }
+
+        Ok(folders)
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Config {
     #[serde(skip)]
     path: PathBuf,
-    #[serde(skip, default = "default_extension_repository")]
-    pub extension_repository: String,
+    /// Extension repositories in priority order
+    #[serde(default = "default_extension_repositories")]
+    pub extension_repositories: Vec<Repository>,
+    /// Base64 encoded ed25519 public key, extensions not signed by it are refused
+    /// unless their repository has its own `public_key`
+    #[serde(default)]
+    pub extension_public_key: Option<String>,
     #[serde(default)]
     pub base_url: Option<String>,
     #[serde(default = "default_port")]
@@ -67,10 +261,34 @@ pub struct Config {
     pub auto_download_chapters: bool,
     #[serde(default = "default_plugin_path")]
     pub plugin_path: String,
+    /// Run each extension in its own process, so a crashing extension can't take down the server.
+    /// A worker is restarted when a call runs past its deadline from `extension_timeouts`
+    #[serde(default)]
+    pub isolate_extensions: bool,
+    /// Number of versions of each extension kept for rollback
+    #[serde(default = "default_extension_versions_to_keep")]
+    pub extension_versions_to_keep: usize,
+    /// Deadlines of extension calls, in seconds
+    #[serde(default)]
+    pub extension_timeouts: Timeouts,
     #[serde(default = "default_local_folders")]
     pub local_path: LocalFolders,
+    /// How deep series and chapters are searched in local folders
+    #[serde(default = "default_local_max_depth")]
+    pub local_max_depth: usize,
+    /// Watch local folders for changes instead of waiting for `update_interval`
+    #[serde(default = "default_local_watch")]
+    pub local_watch: bool,
     #[serde(default = "default_download_path")]
     pub download_path: String,
+    /// Browse the download directory as a local source and link archives found there
+    /// to their chapters, so they can still be read when their source is gone
+    #[serde(default)]
+    pub local_downloads: bool,
+    #[serde(default)]
+    pub download_limits: DownloadLimits,
+    #[serde(default)]
+    pub download_output: DownloadOutput,
     #[serde(default = "default_cache_path")]
     pub cache_path: String,
     #[serde(default)]
@@ -86,7 +304,8 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             path: tanoshi_home().join("config.yml"),
-            extension_repository: default_extension_repository(),
+            extension_repositories: default_extension_repositories(),
+            extension_public_key: None,
             base_url: None,
             port: default_port(),
             database_path: default_database_path(),
@@ -95,8 +314,16 @@ impl Default for Config {
             update_interval: default_update_interval(),
             auto_download_chapters: false,
             plugin_path: default_plugin_path(),
+            isolate_extensions: false,
+            extension_versions_to_keep: default_extension_versions_to_keep(),
+            extension_timeouts: Timeouts::default(),
             local_path: default_local_folders(),
+            local_max_depth: default_local_max_depth(),
+            local_watch: default_local_watch(),
             download_path: default_download_path(),
+            local_downloads: false,
+            download_limits: DownloadLimits::default(),
+            download_output: DownloadOutput::default(),
             cache_path: default_cache_path(),
             enable_playground: false,
             telegram: None,
@@ -119,8 +346,12 @@ fn default_port() -> u16 {
     80
 }
 
-fn default_extension_repository() -> String {
-    "https://raw.githubusercontent.com/faldez/tanoshi-extensions/repository".to_string()
+fn default_extension_repositories() -> Vec<Repository> {
+    vec![Repository {
+        name: "tanoshi-extensions".to_string(),
+        url: "https://raw.githubusercontent.com/faldez/tanoshi-extensions/repository".to_string(),
+        public_key: None,
+    }]
 }
 
 fn default_update_interval() -> u64 {
@@ -156,10 +387,22 @@ fn default_plugin_path() -> String {
     path.display().to_string()
 }
 
+fn default_extension_versions_to_keep() -> usize {
+    3
+}
+
 fn default_local_folders() -> LocalFolders {
     LocalFolders::Single(default_local_path())
 }
 
+fn default_local_max_depth() -> usize {
+    1
+}
+
+fn default_local_watch() -> bool {
+    true
+}
+
 fn default_local_path() -> String {
     let path = tanoshi_home().join("manga");
     if !path.exists() {
@@ -176,6 +419,38 @@ fn default_download_path() -> String {
     path.display().to_string()
 }
 
+fn default_download_concurrency() -> usize {
+    4
+}
+
+fn default_download_concurrency_per_source() -> usize {
+    1
+}
+
+fn default_download_requests_per_second() -> f64 {
+    1.0
+}
+
+fn default_download_max_attempts() -> u32 {
+    5
+}
+
+fn default_download_retry_delay() -> u64 {
+    2
+}
+
+fn default_download_directory() -> String {
+    "{source}/{manga}".to_string()
+}
+
+fn default_download_chapter() -> String {
+    "{number} - {title}".to_string()
+}
+
+fn default_download_page() -> String {
+    "{rank:03}".to_string()
+}
+
 fn default_cache_path() -> String {
     let path = tanoshi_home().join("cache");
     if !path.exists() {
@@ -210,9 +485,59 @@ impl Config {
         }
     }
 
+    /// Local folders with their source id, including the download directory when
+    /// `local_downloads` is enabled
+    pub fn local_folders(&self) -> Result<Vec<(i64, LocalFolder)>, anyhow::Error> {
+        let mut folders = self.local_path.folders()?;
+        if self.local_downloads {
+            if folders.iter().any(|(id, _)| *id == DOWNLOADS_SOURCE_ID) {
+                anyhow::bail!("id {DOWNLOADS_SOURCE_ID} is reserved for downloads");
+            }
+            folders.push((
+                DOWNLOADS_SOURCE_ID,
+                LocalFolder {
+                    name: "Downloads".to_string(),
+                    path: self.download_path.clone(),
+                    max_depth: Some(self.download_output.depth()),
+                    ..Default::default()
+                },
+            ));
+        }
+
+        Ok(folders)
+    }
+
+    /// Where the series index of local source `source_id` is kept
+    pub fn local_index_path(&self, source_id: i64) -> PathBuf {
+        Path::new(&self.cache_path).join(format!("local-{source_id}.index"))
+    }
+
     pub fn save(&self) -> Result<(), anyhow::Error> {
         std::fs::write(&self.path, serde_yaml::to_string(&self)?)?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_download_output_depth() {
+        let output = |directory: &str, chapter: &str| DownloadOutput {
+            directory: directory.to_string(),
+            chapter: chapter.to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(DownloadOutput::default().depth(), 3);
+        assert_eq!(output("{manga}", "{number}").depth(), 2);
+        assert_eq!(output("{source}/{manga}/{volume}", "{number}").depth(), 4);
+        assert_eq!(
+            output("/{source}//{manga}/", "Vol {volume}/{number}").depth(),
+            4
+        );
+        assert_eq!(output("", "").depth(), 1);
+    }
+}
@@ -1,10 +1,14 @@
 use async_trait::async_trait;
+use chrono::NaiveTime;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use sqlx::{Row, SqlitePool};
 
 use crate::{
     domain::{
-        entities::download::{DownloadQueue, DownloadQueueEntry, DownloadedChapter},
+        entities::download::{
+            DownloadQueue, DownloadQueueEntry, DownloadSchedule, DownloadSettings,
+            DownloadedChapter, UndownloadedChapter,
+        },
         repositories::download::{DownloadRepository, DownloadRepositoryError},
     },
     infrastructure::database::Pool,
@@ -152,6 +156,46 @@ impl DownloadRepository for DownloadRepositoryImpl {
         Ok(chapters)
     }
 
+    async fn get_all_downloaded_chapters(
+        &self,
+    ) -> Result<Vec<DownloadedChapter>, DownloadRepositoryError> {
+        let chapters = sqlx::query(
+            r#"
+            SELECT
+                id,
+                source_id,
+                manga_id,
+                title,
+                path,
+                number,
+                scanlator,
+                uploaded,
+                date_added,
+                downloaded_path
+            FROM chapter
+            WHERE downloaded_path IS NOT NULL
+            ORDER BY id"#,
+        )
+        .fetch_all(&self.pool as &SqlitePool)
+        .await?
+        .into_par_iter()
+        .map(|row| DownloadedChapter {
+            id: row.get(0),
+            source_id: row.get(1),
+            manga_id: row.get(2),
+            title: row.get(3),
+            path: row.get(4),
+            number: row.get(5),
+            scanlator: row.get(6),
+            uploaded: row.get(7),
+            date_added: row.get(8),
+            downloaded_path: row.get(9),
+        })
+        .collect();
+
+        Ok(chapters)
+    }
+
     async fn get_chapter_downloaded_path(
         &self,
         chapter_id: i64,
@@ -182,6 +226,42 @@ impl DownloadRepository for DownloadRepositoryImpl {
         Ok(())
     }
 
+    async fn get_undownloaded_chapters(
+        &self,
+    ) -> Result<Vec<UndownloadedChapter>, DownloadRepositoryError> {
+        let chapters = sqlx::query(
+            r#"
+            SELECT
+                chapter.id,
+                manga.source_id,
+                manga.id,
+                manga.title,
+                chapter.title,
+                chapter.number,
+                chapter.scanlator
+            FROM chapter
+            JOIN manga ON manga.id = chapter.manga_id
+            WHERE
+                chapter.downloaded_path IS NULL AND
+                chapter.id NOT IN (SELECT chapter_id FROM download_queue)"#,
+        )
+        .fetch_all(&self.pool as &SqlitePool)
+        .await?
+        .into_par_iter()
+        .map(|row| UndownloadedChapter {
+            chapter_id: row.get(0),
+            source_id: row.get(1),
+            manga_id: row.get(2),
+            manga_title: row.get(3),
+            chapter_title: row.get(4),
+            chapter_number: row.get(5),
+            chapter_scanlator: row.get(6),
+        })
+        .collect();
+
+        Ok(chapters)
+    }
+
     async fn insert_download_queue(
         &self,
         items: &[DownloadQueue],
@@ -246,7 +326,7 @@ impl DownloadRepository for DownloadRepositoryImpl {
                     priority,
                     date_added 
                 FROM download_queue
-                WHERE downloaded IS NOT true
+                WHERE downloaded IS NOT true AND failed IS NOT true
                 ORDER BY priority ASC, date_added ASC, chapter_id ASC, rank ASC
                 LIMIT 1"#,
         )
@@ -269,6 +349,49 @@ impl DownloadRepository for DownloadRepositoryImpl {
         Ok(data)
     }
 
+    async fn get_chapter_download_queue(
+        &self,
+        chapter_id: i64,
+    ) -> Result<Vec<DownloadQueue>, DownloadRepositoryError> {
+        let data = sqlx::query(
+            r#"SELECT
+                    id,
+                    source_id,
+                    source_name,
+                    manga_id,
+                    manga_title,
+                    chapter_id,
+                    chapter_title,
+                    rank,
+                    url,
+                    priority,
+                    date_added
+                FROM download_queue
+                WHERE chapter_id = ? AND failed IS NOT true
+                ORDER BY rank ASC"#,
+        )
+        .bind(chapter_id)
+        .fetch_all(&self.pool as &SqlitePool)
+        .await?
+        .iter()
+        .map(|row| DownloadQueue {
+            id: row.get(0),
+            source_id: row.get(1),
+            source_name: row.get(2),
+            manga_id: row.get(3),
+            manga_title: row.get(4),
+            chapter_id: row.get(5),
+            chapter_title: row.get(6),
+            rank: row.get(7),
+            url: row.get(8),
+            priority: row.get(9),
+            date_added: row.get(10),
+        })
+        .collect();
+
+        Ok(data)
+    }
+
     async fn get_single_chapter_download_status(
         &self,
         chapter_id: i64,
@@ -297,6 +420,37 @@ impl DownloadRepository for DownloadRepositoryImpl {
         Ok(())
     }
 
+    async fn mark_chapter_download_queue_as_failed(
+        &self,
+        chapter_id: i64,
+        error: &str,
+    ) -> Result<(), DownloadRepositoryError> {
+        sqlx::query(
+            r#"UPDATE download_queue SET failed = true, error = ?
+                WHERE chapter_id = ? AND downloaded IS NOT true"#,
+        )
+        .bind(error)
+        .bind(chapter_id)
+        .execute(&self.pool as &SqlitePool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn retry_failed_download_queue(
+        &self,
+        chapter_id: i64,
+    ) -> Result<(), DownloadRepositoryError> {
+        sqlx::query(
+            r#"UPDATE download_queue SET failed = false, error = NULL WHERE chapter_id = ?"#,
+        )
+        .bind(chapter_id)
+        .execute(&self.pool as &SqlitePool)
+        .await?;
+
+        Ok(())
+    }
+
     async fn get_download_queue_last_priority(
         &self,
     ) -> Result<Option<i64>, DownloadRepositoryError> {
@@ -320,7 +474,9 @@ impl DownloadRepository for DownloadRepositoryImpl {
                 dq.chapter_title, 
                 SUM(dq.downloaded),
                 COUNT(1),
-                dq.priority
+                dq.priority,
+                MAX(dq.failed),
+                MAX(dq.error)
             FROM download_queue dq
             GROUP BY dq.chapter_id
             ORDER BY dq.priority ASC, dq.date_added ASC, dq.chapter_id ASC"#,
@@ -338,6 +494,8 @@ impl DownloadRepository for DownloadRepositoryImpl {
             downloaded: row.get(6),
             total: row.get(7),
             priority: row.get(8),
+            failed: row.get(9),
+            error: row.get(10),
         })
         .collect();
 
@@ -401,4 +559,52 @@ impl DownloadRepository for DownloadRepositoryImpl {
 
         Ok(())
     }
+
+    async fn get_download_settings(&self) -> Result<DownloadSettings, DownloadRepositoryError> {
+        let settings = sqlx::query(
+            r#"SELECT paused, schedule_start, schedule_end FROM download_settings WHERE id = 1"#,
+        )
+        .fetch_optional(&self.pool as &SqlitePool)
+        .await?
+        .map(|row| DownloadSettings {
+            paused: row.get(0),
+            schedule: row
+                .get::<Option<NaiveTime>, _>(1)
+                .zip(row.get::<Option<NaiveTime>, _>(2))
+                .map(|(start, end)| DownloadSchedule { start, end }),
+        })
+        .unwrap_or_default();
+
+        Ok(settings)
+    }
+
+    async fn update_download_paused(&self, paused: bool) -> Result<(), DownloadRepositoryError> {
+        sqlx::query(
+            r#"INSERT INTO download_settings (id, paused) VALUES (1, ?)
+                ON CONFLICT(id) DO UPDATE SET paused = excluded.paused"#,
+        )
+        .bind(paused)
+        .execute(&self.pool as &SqlitePool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn update_download_schedule(
+        &self,
+        schedule: Option<DownloadSchedule>,
+    ) -> Result<(), DownloadRepositoryError> {
+        sqlx::query(
+            r#"INSERT INTO download_settings (id, schedule_start, schedule_end) VALUES (1, ?, ?)
+                ON CONFLICT(id) DO UPDATE SET
+                    schedule_start = excluded.schedule_start,
+                    schedule_end = excluded.schedule_end"#,
+        )
+        .bind(schedule.map(|schedule| schedule.start))
+        .bind(schedule.map(|schedule| schedule.end))
+        .execute(&self.pool as &SqlitePool)
+        .await?;
+
+        Ok(())
+    }
 }
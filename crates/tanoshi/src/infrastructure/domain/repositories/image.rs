@@ -4,9 +4,12 @@ use async_trait::async_trait;
 
 use http::{HeaderMap, HeaderValue};
 
-use crate::domain::{
-    entities::image::Image,
-    repositories::image::{ImageRepository, ImageRepositoryError},
+use crate::{
+    domain::{
+        entities::image::Image,
+        repositories::image::{ImageRepository, ImageRepositoryError},
+    },
+    infrastructure::local,
 };
 
 #[derive(Default, Clone)]
@@ -103,4 +106,25 @@ impl ImageRepository for ImageRepositoryImpl {
             data: data.into(),
         })
     }
+
+    async fn fetch_image_from_pdf<P>(
+        &self,
+        pdf: P,
+        page: u32,
+    ) -> Result<Image, ImageRepositoryError>
+    where
+        P: AsRef<Path> + std::marker::Send,
+    {
+        let pdf = pdf.as_ref().to_path_buf();
+        let (content_type, data) =
+            tokio::task::spawn_blocking(move || local::pdf::get_page_image(&pdf, page))
+                .await
+                .map_err(|e| ImageRepositoryError::Other(format!("{e}")))?
+                .map_err(|e| ImageRepositoryError::Other(format!("{e}")))?;
+
+        Ok(Image {
+            content_type,
+            data: data.into(),
+        })
+    }
 }
@@ -1,9 +1,12 @@
-use std::str::FromStr;
+use std::{collections::HashMap, str::FromStr};
 
 use async_trait::async_trait;
 use serde::Deserialize;
 use tanoshi_lib::prelude::Version;
-use tanoshi_vm::prelude::ExtensionManager;
+use tanoshi_vm::{
+    prelude::{is_native_compatible, ExtensionManager, FileIndex, Repository},
+    WASM_TARGET,
+};
 
 use crate::domain::{
     entities::source::Source,
@@ -18,7 +21,39 @@ pub struct SourceDto {
     pub version: String,
     pub rustc_version: String,
     pub lib_version: String,
+    /// Set when the extension is built with `tanoshi_lib::ffi` stable interface
+    #[serde(default)]
+    pub abi_version: Option<u32>,
     pub icon: String,
+    /// Keyed by target, a `wasm32-wasi` build runs on any host
+    #[serde(default)]
+    pub files: HashMap<String, FileIndex>,
+}
+
+impl SourceDto {
+    /// Either the native build can be loaded, see `is_native_compatible`, or there is a
+    /// `wasm32-wasi` build, which wasm-only extensions report as their `rustc_version`.
+    /// Lib version of a legacy native build is compared by semver, a stable or wasm build
+    /// only needs the same major version.
+    fn is_compatible(&self) -> bool {
+        let (host, required) = match (
+            Version::from_str(tanoshi_lib::LIB_VERSION),
+            Version::from_str(&self.lib_version),
+        ) {
+            (Ok(host), Ok(required)) => (host, required),
+            _ => return false,
+        };
+
+        let native = is_native_compatible(&self.rustc_version, self.abi_version);
+        if self.rustc_version == WASM_TARGET
+            || self.files.contains_key(WASM_TARGET)
+            || (native && self.abi_version.is_some())
+        {
+            host.is_serde_compatible_with(&required)
+        } else {
+            native && host.is_compatible_with(&required)
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -34,6 +69,14 @@ impl SourceRepositoryImpl {
     }
 }
 
+impl SourceRepositoryImpl {
+    async fn fetch_indexes(&self, repositories: &[Repository]) -> Vec<(Repository, SourceDto)> {
+        self.extension_manager
+            .fetch_indexes(repositories, |index: &SourceDto| index.id)
+            .await
+    }
+}
+
 #[async_trait]
 impl SourceRepository for SourceRepositoryImpl {
     async fn installed_sources(&self) -> Result<Vec<Source>, SourceRepositoryError> {
@@ -45,6 +88,10 @@ impl SourceRepository for SourceRepositoryImpl {
             .map(|s| s.into())
             .collect::<Vec<Source>>();
 
+        for source in sources.iter_mut() {
+            source.repository = self.extension_manager.get_repository(source.id).await?;
+        }
+
         sources.sort_by(|a, b| a.id.cmp(&b.id));
 
         Ok(sources)
@@ -52,16 +99,13 @@ impl SourceRepository for SourceRepositoryImpl {
 
     async fn available_sources(
         &self,
-        repo_url: &str,
+        repositories: &[Repository],
         filter_installed: bool,
     ) -> Result<Vec<Source>, SourceRepositoryError> {
-        let source_indexes: Vec<SourceDto> = reqwest::get(&format!("{repo_url}/index.json"))
-            .await?
-            .json()
-            .await?;
+        let source_indexes = self.fetch_indexes(repositories).await;
 
         let mut sources: Vec<Source> = vec![];
-        for index in source_indexes {
+        for (repo, index) in source_indexes {
             if filter_installed && self.extension_manager.exists(index.id).await? {
                 continue;
             }
@@ -75,6 +119,7 @@ impl SourceRepository for SourceRepositoryImpl {
                 lib_version: index.lib_version,
                 icon: index.icon,
                 has_update: false,
+                repository: Some(repo.name),
             });
         }
 
@@ -82,70 +127,72 @@ impl SourceRepository for SourceRepositoryImpl {
     }
 
     async fn get_source_by_id(&self, id: i64) -> Result<Source, SourceRepositoryError> {
-        let source = self.extension_manager.get_source_info(id)?;
-        Ok(source.into())
+        let mut source: Source = self.extension_manager.get_source_info(id)?.into();
+        source.repository = self.extension_manager.get_repository(id).await?;
+        Ok(source)
     }
 
-    async fn install_source(&self, repo_url: &str, id: i64) -> Result<(), SourceRepositoryError> {
+    async fn install_source(
+        &self,
+        repositories: &[Repository],
+        id: i64,
+    ) -> Result<(), SourceRepositoryError> {
         if self.extension_manager.exists(id).await? {
             return Err(SourceRepositoryError::Other(
                 "source installed, use updateSource to update".to_string(),
             ));
         }
 
-        let source_indexes: Vec<SourceDto> = reqwest::get(format!("{repo_url}/index.json"))
-            .await?
-            .json()
-            .await?;
-
-        let source = source_indexes
-            .iter()
-            .find(|index| index.id == id)
+        let (repo, source) = self
+            .fetch_indexes(repositories)
+            .await
+            .into_iter()
+            .find(|(_, index)| index.id == id)
             .ok_or(SourceRepositoryError::NotFound)?;
 
-        if source.rustc_version != tanoshi_lib::RUSTC_VERSION
-            || source.lib_version != tanoshi_lib::LIB_VERSION
-        {
+        if !source.is_compatible() {
             return Err(SourceRepositoryError::Other(
                 "Incompatible version, update tanoshi server".to_string(),
             ));
         }
 
-        self.extension_manager
-            .install(repo_url, &source.name)
-            .await?;
+        self.extension_manager.install(&repo, &source.name).await?;
 
         Ok(())
     }
 
-    async fn update_source(&self, repo_url: &str, id: i64) -> Result<(), SourceRepositoryError> {
+    async fn update_source(
+        &self,
+        repositories: &[Repository],
+        id: i64,
+    ) -> Result<(), SourceRepositoryError> {
         let installed_source = self.extension_manager.get_source_info(id)?;
 
-        let source_indexes: Vec<SourceDto> = reqwest::get(format!("{repo_url}/index.json"))
-            .await?
-            .json()
-            .await?;
-        let source = source_indexes
-            .iter()
-            .find(|index| index.id == id)
+        let (repo, source) = self
+            .fetch_indexes(repositories)
+            .await
+            .into_iter()
+            .find(|(_, index)| index.id == id)
             .ok_or(SourceRepositoryError::NotFound)?;
 
-        if Version::from_str(installed_source.version)? == Version::from_str(&source.version)? {
+        if Version::from_str(&installed_source.version)? == Version::from_str(&source.version)? {
             return Err(SourceRepositoryError::Other("No new version".to_string()));
         }
 
-        if source.rustc_version != tanoshi_lib::RUSTC_VERSION
-            || source.lib_version != tanoshi_lib::LIB_VERSION
-        {
+        if !source.is_compatible() {
             return Err(SourceRepositoryError::Other(
                 "Incompatible version, update tanoshi server".to_string(),
             ));
         }
 
-        self.extension_manager.remove(id).await?;
-        self.extension_manager
-            .install(repo_url, &source.name)
-            .await?;
+        // installed version is kept, running source is swapped once the new one is loaded
+        self.extension_manager.install(&repo, &source.name).await?;
+
+        Ok(())
+    }
+
+    async fn rollback_source(&self, id: i64, version: &str) -> Result<(), SourceRepositoryError> {
+        self.extension_manager.rollback(id, version).await?;
 
         Ok(())
     }
@@ -1,7 +1,9 @@
 use std::{
     collections::HashMap,
+    ffi::OsStr,
     fs::{DirEntry, ReadDir},
     path::{Path, PathBuf},
+    sync::Arc,
     time::UNIX_EPOCH,
 };
 
@@ -10,15 +12,42 @@ use async_trait::async_trait;
 use fancy_regex::Regex;
 use mime_guess::mime;
 use serde::{Deserialize, Serialize};
-use tanoshi_lib::prelude::{ChapterInfo, Extension, Input, Lang, MangaInfo, SourceInfo};
+use tanoshi_lib::prelude::{
+    ChapterInfo, Extension, Input, InputType, Lang, MangaInfo, SourceInfo, TriState,
+};
+
+pub mod chapter_name;
+pub mod comicinfo;
+pub mod epub;
+pub mod files;
+pub mod index;
+pub mod pdf;
+
+use chapter_name::ChapterName;
+use comicinfo::ComicInfo;
+use files::{compare_pages, FileFilter, PageFilter};
+use index::LocalIndex;
 
 // list of supported files, other archive may works but no tested
 pub static SUPPORTED_FILES: phf::Set<&'static str> = phf::phf_set! {
     "cbz",
     "cbr",
-    "cb7"
+    "cb7",
+    "pdf",
+    "epub"
 };
 
+// extensions are matched case-insensitively, e.g. `Vol1.CBZ`
+fn is_supported_extension(extension: &OsStr) -> bool {
+    SUPPORTED_FILES.contains(extension.to_string_lossy().to_lowercase().as_str())
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .map(|ext| ext.eq_ignore_ascii_case(extension))
+        .unwrap_or(false)
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
 pub struct LocalMangaInfo {
     pub title: Option<String>,
@@ -33,12 +62,85 @@ pub struct Local {
     id: i64,
     name: String,
     path: PathBuf,
+    max_depth: usize,
+    nsfw: bool,
+    languages: Vec<String>,
+    filter: FileFilter,
+    page_filter: PageFilter,
+    index_path: Option<PathBuf>,
+    index: Arc<LocalIndex>,
 }
 
 impl Local {
     pub fn new<P: AsRef<Path>>(id: i64, name: String, path: P) -> Self {
         let path = PathBuf::new().join(path);
-        Self { id, name, path }
+        Self {
+            id,
+            name,
+            index: Arc::new(LocalIndex::new(&path, 1, FileFilter::default(), None)),
+            path,
+            max_depth: 1,
+            nsfw: false,
+            languages: vec![],
+            filter: FileFilter::default(),
+            page_filter: PageFilter::default(),
+            index_path: None,
+        }
+    }
+
+    /// How deep series are searched from the root folder, and chapters from a series folder.
+    ///
+    /// A folder at `max_depth` is always taken as a series or chapter, this is the only
+    /// behavior with the default of 1.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth.max(1);
+        self.reset_index();
+        self
+    }
+
+    pub fn with_nsfw(mut self, nsfw: bool) -> Self {
+        self.nsfw = nsfw;
+        self
+    }
+
+    /// Languages of the manga in this folder, all languages if empty
+    pub fn with_languages(mut self, languages: Vec<String>) -> Self {
+        self.languages = languages;
+        self
+    }
+
+    /// Skip files and folders when searching series and chapters
+    pub fn with_file_filter(mut self, filter: FileFilter) -> Self {
+        self.filter = filter;
+        self.reset_index();
+        self
+    }
+
+    /// Skip images inside chapters that are not pages
+    pub fn with_page_filter(mut self, page_filter: PageFilter) -> Self {
+        self.page_filter = page_filter;
+        self
+    }
+
+    /// Keep the series index in `index_path` between restarts
+    pub fn with_index_path<P: AsRef<Path>>(mut self, index_path: P) -> Self {
+        self.index_path = Some(index_path.as_ref().to_path_buf());
+        self.reset_index();
+        self
+    }
+
+    fn reset_index(&mut self) {
+        self.index = Arc::new(LocalIndex::new(
+            &self.path,
+            self.max_depth,
+            self.filter.clone(),
+            self.index_path.clone(),
+        ));
+    }
+
+    /// Series index of this source, rebuild it with `LocalIndex::build` when files change
+    pub fn index(&self) -> Arc<LocalIndex> {
+        self.index.clone()
     }
 }
 fn default_cover_url() -> String {
@@ -47,53 +149,170 @@ fn default_cover_url() -> String {
 
 fn filter_supported_files_and_folders(entry: Result<DirEntry, std::io::Error>) -> Option<DirEntry> {
     let entry = entry.ok()?;
-    if entry.path().is_dir()
-        || SUPPORTED_FILES.contains(&entry.path().extension()?.to_string_lossy().to_string())
-    {
+    if entry.path().is_dir() || is_supported_extension(entry.path().extension()?) {
         Some(entry)
     } else {
         None
     }
 }
 
-// find first image from an archvie
+// find first page of an archive
 fn find_cover_from_archive(path: &Path) -> String {
-    let source = match std::fs::File::open(path) {
-        Ok(file) => file,
+    match get_pages_from_archive(path, &PageFilter::default()) {
+        Ok(pages) => pages.into_iter().next().unwrap_or_else(default_cover_url),
         Err(e) => {
             error!("error open {}, reason {}", path.display(), e);
-            return default_cover_url();
-        }
-    };
-
-    let mut cover_url = default_cover_url();
-    if let Ok(files) = compress_tools::list_archive_files(source) {
-        for file in files {
-            let file = PathBuf::from(&file);
-            let res = mime_guess::from_path(&file);
-            debug!("{} {:?}", file.display(), res.first());
-            if res
-                .first()
-                .map(|m| m.type_() == mime::IMAGE)
-                .unwrap_or(false)
-            {
-                cover_url = path.join(file).display().to_string();
-                break;
-            }
+            default_cover_url()
         }
     }
-
-    cover_url
 }
 
-// find first image from a directory
+// find first image from a directory, descending into nested volume or chapter directories
 fn find_cover_from_dir(path: &Path) -> String {
-    path.read_dir()
+    let entry = match path
+        .read_dir()
         .ok()
         .map(sort_dir)
         .and_then(|dir| dir.into_iter().next())
-        .map(|entry| entry.path().display().to_string())
-        .unwrap_or_else(default_cover_url)
+    {
+        Some(entry) => entry.path(),
+        None => return default_cover_url(),
+    };
+
+    if entry.is_dir() {
+        find_cover_from_dir(&entry)
+    } else if is_supported_file(&entry) {
+        find_cover_from_archive(&entry)
+    } else {
+        entry.display().to_string()
+    }
+}
+
+fn is_supported_file(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .map(is_supported_extension)
+            .unwrap_or(false)
+}
+
+fn is_image(path: &Path) -> bool {
+    mime_guess::from_path(path)
+        .first()
+        .map(|m| m.type_() == mime::IMAGE)
+        .unwrap_or(false)
+}
+
+// a directory with images is a chapter
+fn is_chapter_dir(path: &Path) -> bool {
+    path.read_dir()
+        .map(|mut dir| {
+            dir.any(|entry| {
+                entry
+                    .map(|entry| entry.path().is_file() && is_image(&entry.path()))
+                    .unwrap_or(false)
+            })
+        })
+        .unwrap_or(false)
+}
+
+fn volume_number(path: &Path) -> Option<f64> {
+    let re = Regex::new(r"(?i)^(?:volume|vol|tome|v)\.?\s*(\d+(?:\.\d+)?)").ok()?;
+    let name = path.file_name()?.to_string_lossy().to_string();
+    let captures = re.captures(name.trim()).ok()??;
+    captures.get(1)?.as_str().parse().ok()
+}
+
+// a directory with chapters, or with volume directories, is a series,
+// anything else is a grouping like publisher or genre
+fn is_series_dir(path: &Path) -> bool {
+    path.read_dir()
+        .map(|dir| {
+            dir.filter_map(Result::ok).any(|entry| {
+                let path = entry.path();
+                is_supported_file(&path)
+                    || (path.is_dir() && (volume_number(&path).is_some() || is_chapter_dir(&path)))
+            })
+        })
+        .unwrap_or(false)
+}
+
+fn find_series(
+    path: &Path,
+    depth: usize,
+    max_depth: usize,
+    filter: &FileFilter,
+    series: &mut Vec<PathBuf>,
+) {
+    let dir = match path.read_dir() {
+        Ok(dir) => sort_dir(dir),
+        Err(_) => return,
+    };
+
+    for entry in dir {
+        find_series_in(&entry.path(), depth, max_depth, filter, series);
+    }
+}
+
+// `path` is a series itself, or a grouping directory of series
+fn find_series_in(
+    path: &Path,
+    depth: usize,
+    max_depth: usize,
+    filter: &FileFilter,
+    series: &mut Vec<PathBuf>,
+) {
+    if !filter.allows(path) {
+        return;
+    }
+    if is_supported_file(path) || (path.is_dir() && (depth >= max_depth || is_series_dir(path))) {
+        series.push(path.to_path_buf());
+    } else if path.is_dir() {
+        find_series(path, depth + 1, max_depth, filter, series);
+    }
+}
+
+// chapter files and directories of a series, with the volume of their volume directory if any
+fn find_chapters(
+    path: &Path,
+    volume: Option<f64>,
+    depth: usize,
+    max_depth: usize,
+    filter: &FileFilter,
+    chapters: &mut Vec<(PathBuf, Option<f64>)>,
+) {
+    let dir = match path.read_dir() {
+        Ok(dir) => dir,
+        Err(_) => return,
+    };
+
+    for entry in dir.filter_map(filter_supported_files_and_folders) {
+        let path = entry.path();
+        if !filter.allows(&path) {
+            continue;
+        }
+        if path.is_dir() && depth < max_depth && !is_chapter_dir(&path) {
+            let volume = volume_number(&path).or(volume);
+            find_chapters(&path, volume, depth + 1, max_depth, filter, chapters);
+        } else {
+            chapters.push((path, volume));
+        }
+    }
+}
+
+// a series can be a single chapter file
+fn find_series_chapters(
+    path: &Path,
+    max_depth: usize,
+    filter: &FileFilter,
+) -> Vec<(PathBuf, Option<f64>)> {
+    let mut chapters = vec![];
+    if path.is_file() {
+        chapters.push((path.to_path_buf(), None));
+    } else {
+        find_chapters(path, None, 1, max_depth, filter, &mut chapters);
+    }
+    chapters
 }
 
 // find details from an archvie
@@ -137,6 +356,14 @@ fn sort_read_dir_with_reverse(dir: ReadDir, reverse: bool) -> Vec<DirEntry> {
 }
 
 fn find_cover_url(entry: &Path) -> String {
+    // cover chosen with `write_manga_info`
+    if let Some(cover_path) = find_details(entry)
+        .and_then(|object| serde_json::from_slice::<LocalMangaInfo>(&object).ok())
+        .and_then(|info| info.cover_path)
+    {
+        return entry.join(cover_path).display().to_string();
+    }
+
     if entry.is_file() {
         return find_cover_from_archive(entry);
     }
@@ -177,37 +404,61 @@ fn find_details(path: &Path) -> Option<Vec<u8>> {
     }
 }
 
-pub fn get_pages_from_archive(path: &Path) -> Result<Vec<String>, anyhow::Error> {
+// pdf and epub pages are in reading order, pages of other archives are sorted naturally by
+// `compare_pages`, the same order as pages of a directory
+pub fn get_pages_from_archive(
+    path: &Path,
+    filter: &PageFilter,
+) -> Result<Vec<String>, anyhow::Error> {
+    if has_extension(path, "pdf") {
+        return pdf::get_pages(path);
+    }
+    if has_extension(path, "epub") {
+        return epub::get_pages(path);
+    }
+
     let source = std::fs::File::open(path)?;
     match compress_tools::list_archive_files(source) {
         Ok(files) => {
-            let pages = files
+            let mut pages: Vec<String> = files
                 .into_iter()
-                .filter(|p| {
-                    mime_guess::from_path(&p)
-                        .first()
-                        .map(|m| m.type_() == mime::IMAGE)
-                        .unwrap_or(false)
-                })
-                .map(|p| path.join(p).display().to_string())
+                .filter(|p| is_image(Path::new(p)) && filter.allows(p))
                 .collect();
-            Ok(pages)
+            pages.sort_by(|a, b| compare_pages(a, b));
+            Ok(pages
+                .into_iter()
+                .map(|p| path.join(p).display().to_string())
+                .collect())
         }
         Err(e) => Err(anyhow::anyhow!("{}", e)),
     }
 }
 
-fn get_pages_from_dir(path: &Path) -> Result<Vec<String>, anyhow::Error> {
-    let pages = path
+pub fn get_pages_from_dir(path: &Path, filter: &PageFilter) -> Result<Vec<String>, anyhow::Error> {
+    let mut pages: Vec<String> = path
         .read_dir()?
         .into_iter()
         .filter_map(Result::ok)
-        .filter_map(|f| (f.path().is_file()).then(|| f.path().display().to_string()))
+        .map(|f| f.path())
+        .filter(|p| {
+            p.is_file()
+                && is_image(p)
+                && p.file_name()
+                    .map(|name| filter.allows(&name.to_string_lossy()))
+                    .unwrap_or(false)
+        })
+        .map(|p| p.display().to_string())
         .collect();
+    pages.sort_by(|a, b| compare_pages(a, b));
     Ok(pages)
 }
 
-fn map_entry_to_chapter(source_id: i64, path: &Path) -> Option<ChapterInfo> {
+// `comic_info` is read when the series is indexed
+fn map_entry_to_chapter(
+    source_id: i64,
+    path: &Path,
+    comic_info: Option<ComicInfo>,
+) -> Option<ChapterInfo> {
     let modified = match path
         .metadata()
         .ok()
@@ -219,30 +470,232 @@ fn map_entry_to_chapter(source_id: i64, path: &Path) -> Option<ChapterInfo> {
             return None;
         }
     };
-    let number_re = match Regex::new(
-        r"(?i)(?<=v)(\d+)|(?<=volume)\s*(\d+)|(?<=vol)\s*(\d+)|(?<=\s)(\d+)|(\d+)",
-    ) {
-        Ok(re) => re,
-        Err(_) => {
-            return None;
-        }
-    };
     let file_name = path.file_stem()?.to_string_lossy().to_string();
-    let number = match number_re.find(&file_name).ok().and_then(|m| m) {
-        Some(mat) => mat.as_str().parse().unwrap_or(0_f64),
-        None => 10000_f64,
-    };
+    let name = ChapterName::parse(&file_name);
 
-    Some(ChapterInfo {
+    let mut chapter = ChapterInfo {
         source_id,
         title: file_name,
         path: format!("{}", path.display()),
-        number,
+        // a file without chapter number is usually a whole volume
+        number: name.number.or(name.volume).unwrap_or(0.0),
         scanlator: None,
         uploaded: modified as i64,
+        volume: name.volume,
+        number_end: name.number_end,
+        extra: name.extra,
+    };
+
+    if let Some(info) = comic_info {
+        if let Some(number) = info.number() {
+            chapter.number = number;
+        }
+        if let Some(title) = info.title() {
+            chapter.title = title.to_string();
+        }
+        if let Some(volume) = info.volume() {
+            chapter.title = format!("Vol. {volume} {}", chapter.title);
+            if let Ok(volume) = volume.parse() {
+                chapter.volume = Some(volume);
+            }
+        }
+        chapter.scanlator = info.scanlator().map(|scanlator| scanlator.to_string());
+        if let Some(uploaded) = info.uploaded() {
+            chapter.uploaded = uploaded;
+        }
+    }
+
+    Some(chapter)
+}
+
+// a manga directory usually has no ComicInfo.xml, use the one from its first chapter
+fn find_comic_info(path: &Path) -> Option<ComicInfo> {
+    if has_extension(path, "pdf") {
+        return None;
+    }
+
+    ComicInfo::read(path).or_else(|| {
+        path.read_dir()
+            .ok()
+            .map(sort_dir)?
+            .into_iter()
+            .filter_map(|entry| filter_supported_files_and_folders(Ok(entry)))
+            .find_map(|entry| ComicInfo::read(&entry.path()))
     })
 }
 
+impl LocalMangaInfo {
+    // fields set in `other` replace those of self
+    fn merge(self, other: LocalMangaInfo) -> Self {
+        Self {
+            title: other.title.or(self.title),
+            author: other.author.or(self.author),
+            genre: other.genre.or(self.genre),
+            status: other.status.or(self.status),
+            description: other.description.or(self.description),
+            cover_path: other.cover_path.or(self.cover_path),
+        }
+    }
+}
+
+fn write_details_to_dir(path: &Path, info: LocalMangaInfo) -> Result<()> {
+    let existing = find_details_from_dir(path)
+        .and_then(|object| serde_json::from_slice::<LocalMangaInfo>(&object).ok())
+        .unwrap_or_default();
+    let info = existing.merge(info);
+
+    if let Some(cover_path) = info.cover_path.as_ref() {
+        let cover = path.join(cover_path).canonicalize()?;
+        if !cover.starts_with(path.canonicalize()?) || !cover.is_file() || !is_image(&cover) {
+            return Err(anyhow!(
+                "{cover_path} is not an image in {}",
+                path.display()
+            ));
+        }
+    }
+
+    let tmp = path.join("details.json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(&info)?)?;
+    std::fs::rename(&tmp, path.join("details.json"))?;
+
+    Ok(())
+}
+
+// copy archive at `path` to `tmp`, replacing or adding `entries`
+fn write_zip_entries(path: &Path, tmp: &Path, entries: &[(&str, Vec<u8>)]) -> Result<()> {
+    use std::io::Write;
+
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(path)?)?;
+    let mut writer = zip::ZipWriter::new(std::fs::File::create(tmp)?);
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        if !entries.iter().any(|(name, _)| file.name() == *name) {
+            writer.raw_copy_file(file)?;
+        }
+    }
+    for (name, data) in entries {
+        writer.start_file(
+            *name,
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored),
+        )?;
+        writer.write_all(data)?;
+    }
+    writer.finish()?;
+
+    Ok(())
+}
+
+fn write_comic_info_to_archive(path: &Path, info: LocalMangaInfo) -> Result<()> {
+    if !has_extension(path, "cbz") {
+        return Err(anyhow!("metadata can only be written to folders and cbz"));
+    }
+
+    let names: Vec<String> = zip::ZipArchive::new(std::fs::File::open(path)?)?
+        .file_names()
+        .map(|name| name.to_string())
+        .collect();
+    if let Some(cover_path) = info.cover_path.as_ref() {
+        if !names.contains(cover_path) || !is_image(Path::new(cover_path)) {
+            return Err(anyhow!(
+                "{cover_path} is not an image in {}",
+                path.display()
+            ));
+        }
+    }
+
+    let mut xml = comicinfo::read_xml(path)
+        .unwrap_or_else(|| "<?xml version=\"1.0\"?>\n<ComicInfo>\n</ComicInfo>\n".to_string());
+    let elements = [
+        ("Series", info.title.clone()),
+        (
+            "Writer",
+            info.author.as_ref().map(|author| author.join(", ")),
+        ),
+        ("Genre", info.genre.as_ref().map(|genre| genre.join(", "))),
+        ("Status", info.status.clone()),
+        ("Summary", info.description.clone()),
+    ];
+    for (name, value) in elements {
+        if let Some(value) = value {
+            xml = comicinfo::set_element(&xml, name, &value);
+        }
+    }
+    let mut entries = vec![(comicinfo::FILE_NAME, xml.into_bytes())];
+
+    // details.json takes precedence over ComicInfo.xml, so it is updated as well when present,
+    // ComicInfo.xml has no field for the cover so it is only stored there
+    if names.iter().any(|name| name == "details.json") || info.cover_path.is_some() {
+        let existing = find_details_from_archive(path)
+            .and_then(|object| serde_json::from_slice::<LocalMangaInfo>(&object).ok())
+            .unwrap_or_default();
+        entries.push((
+            "details.json",
+            serde_json::to_vec_pretty(&existing.merge(info))?,
+        ));
+    }
+
+    let tmp = path.with_extension("cbz.tmp");
+    if let Err(e) = write_zip_entries(path, &tmp, &entries) {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e);
+    }
+    std::fs::rename(&tmp, path)?;
+
+    Ok(())
+}
+
+/// Write metadata of the manga at `path` inside local folder `root`, to details.json of a
+/// folder or ComicInfo.xml of a cbz. Fields left `None` are kept. `cover_path` is relative to
+/// the folder, or an entry of the cbz which is then kept in details.json of the cbz.
+pub fn write_manga_info(root: &Path, path: &Path, info: LocalMangaInfo) -> Result<()> {
+    if !path.canonicalize()?.starts_with(root.canonicalize()?) {
+        return Err(anyhow!("{} is not in {}", path.display(), root.display()));
+    }
+
+    if path.is_dir() {
+        write_details_to_dir(path, info)
+    } else {
+        write_comic_info_to_archive(path, info)
+    }
+}
+
+// genres of a manga, from details.json or else ComicInfo.xml
+fn find_genres(path: &Path) -> Vec<String> {
+    find_details(path)
+        .and_then(|object| serde_json::from_slice::<LocalMangaInfo>(&object).ok())
+        .and_then(|info| info.genre)
+        .or_else(|| find_comic_info(path).and_then(|info| info.genres()))
+        .unwrap_or_default()
+}
+
+const SORT_FILTER: &str = "Sort";
+const SORT_VALUES: [&str; 3] = ["Name", "Modified", "Chapters"];
+const GENRE_FILTER: &str = "Genre";
+const FORMAT_FILTER: &str = "Format";
+const FORMAT_VALUES: [&str; 7] = ["All", "Folder", "CBZ", "CBR", "CB7", "PDF", "EPUB"];
+
+fn entry_has_format(entry: &index::IndexEntry, format: &str) -> bool {
+    let path = Path::new(&entry.path);
+    match format {
+        "All" => true,
+        "Folder" => path.is_dir(),
+        format => path.is_file() && has_extension(path, format),
+    }
+}
+
+fn sort_entries(entries: &mut Vec<&index::IndexEntry>, sort: &str, asc: bool) {
+    match sort {
+        "Name" => entries
+            .sort_by(|a, b| human_sort::compare(&a.title.to_lowercase(), &b.title.to_lowercase())),
+        "Modified" => entries.sort_by_key(|entry| entry.modified),
+        "Chapters" => entries.sort_by_key(|entry| entry.chapters),
+        _ => {}
+    }
+    if !asc {
+        entries.reverse();
+    }
+}
+
 #[async_trait]
 impl Extension for Local {
     fn get_source_info(&self) -> SourceInfo {
@@ -250,15 +703,59 @@ impl Extension for Local {
             id: self.id,
             name: self.name.clone(),
             url: format!("{}", self.path.display()),
-            version: "0.0.0",
-            icon: "/icons/192.png",
-            languages: Lang::All,
-            nsfw: false,
+            version: "0.0.0".to_string(),
+            icon: "/icons/192.png".to_string(),
+            languages: match self.languages.as_slice() {
+                [] => Lang::All,
+                [language] => Lang::Single(language.clone()),
+                languages => Lang::Multi(languages.to_vec()),
+            },
+            nsfw: self.nsfw,
         }
     }
 
     fn filter_list(&self) -> Vec<Input> {
-        vec![]
+        let mut genres: Vec<String> = self
+            .index
+            .entries()
+            .map(|entries| {
+                entries
+                    .iter()
+                    .flat_map(|entry| entry.genres.iter().cloned())
+                    .collect()
+            })
+            .unwrap_or_default();
+        genres.sort_by_key(|genre| genre.to_lowercase());
+        genres.dedup_by_key(|genre| genre.to_lowercase());
+
+        vec![
+            Input::Sort {
+                name: SORT_FILTER.to_string(),
+                values: SORT_VALUES
+                    .iter()
+                    .map(|value| InputType::from(*value))
+                    .collect(),
+                selection: Some((0, true)),
+            },
+            Input::Group {
+                name: GENRE_FILTER.to_string(),
+                state: genres
+                    .into_iter()
+                    .map(|genre| Input::State {
+                        name: genre,
+                        selected: None,
+                    })
+                    .collect(),
+            },
+            Input::Select {
+                name: FORMAT_FILTER.to_string(),
+                values: FORMAT_VALUES
+                    .iter()
+                    .map(|value| InputType::from(*value))
+                    .collect(),
+                state: Some(0),
+            },
+        ]
     }
 
     fn headers(&self) -> HashMap<String, String> {
@@ -285,51 +782,79 @@ impl Extension for Local {
         &self,
         page: i64,
         query: Option<String>,
-        _filters: Option<Vec<Input>>,
+        filters: Option<Vec<Input>>,
     ) -> Result<Vec<MangaInfo>> {
         let id = self.id;
-        let path = self.path.clone();
         let offset = (page - 1) * 20;
 
-        let read_dir = match std::fs::read_dir(&path) {
-            Ok(read_dir) => read_dir,
-            Err(e) => {
-                return Err(anyhow!("{}", e));
-            }
-        };
+        let entries = self.index.entries()?;
 
-        let mut data: Box<dyn Iterator<Item = _>> = Box::new(
-            read_dir
-                .into_iter()
-                .filter_map(filter_supported_files_and_folders),
-        );
+        let mut data: Vec<&index::IndexEntry> = entries.iter().collect();
 
         if let Some(keyword) = query {
-            data = Box::new(data.filter(move |entry| {
-                entry
-                    .file_name()
-                    .to_string_lossy()
-                    .to_lowercase()
-                    .contains(&keyword)
-            }));
+            let keyword = keyword.to_lowercase();
+            data.retain(|entry| {
+                entry.title.to_lowercase().contains(&keyword)
+                    || Path::new(&entry.path)
+                        .file_name()
+                        .map(|name| name.to_string_lossy().to_lowercase().contains(&keyword))
+                        .unwrap_or(false)
+            });
+        }
+
+        for filter in filters.unwrap_or_default() {
+            match filter {
+                Input::Group { name, state } if name == GENRE_FILTER => {
+                    for genre in state {
+                        let (genre, included) = match genre {
+                            Input::State {
+                                name,
+                                selected: Some(selected),
+                            } if selected != TriState::Ignored => {
+                                (name.to_lowercase(), selected == TriState::Included)
+                            }
+                            _ => continue,
+                        };
+                        data.retain(|entry| {
+                            entry.genres.iter().any(|g| g.to_lowercase() == genre) == included
+                        });
+                    }
+                }
+                Input::Select {
+                    name,
+                    state: Some(state),
+                    ..
+                } if name == FORMAT_FILTER => {
+                    if let Some(format) = FORMAT_VALUES.get(state as usize) {
+                        data.retain(|entry| entry_has_format(entry, format));
+                    }
+                }
+                Input::Sort {
+                    name,
+                    selection: Some((index, asc)),
+                    ..
+                } if name == SORT_FILTER => {
+                    if let Some(sort) = SORT_VALUES.get(index as usize) {
+                        sort_entries(&mut data, sort, asc);
+                    }
+                }
+                _ => {}
+            }
         }
 
         let manga = data
+            .into_iter()
             .skip(offset as _)
             .take(20)
             .map(|entry| MangaInfo {
                 source_id: id,
-                title: entry
-                    .path()
-                    .file_stem()
-                    .map(|s| s.to_string_lossy().to_string())
-                    .unwrap_or_else(|| "".to_string()),
+                title: entry.title.clone(),
                 author: vec![],
-                genre: vec![],
+                genre: entry.genres.clone(),
                 status: None,
                 description: None,
-                path: entry.path().to_string_lossy().to_string(),
-                cover_url: find_cover_url(&entry.path()),
+                path: entry.path.clone(),
+                cover_url: entry.cover_url.clone(),
             })
             .collect::<Vec<_>>();
 
@@ -357,6 +882,25 @@ impl Extension for Local {
             cover_url,
         };
 
+        if let Some(info) = find_comic_info(&path) {
+            if let Some(series) = info.series() {
+                manga.title = series.to_string();
+            }
+            if let Some(author) = info.authors() {
+                manga.author = author;
+            }
+            if let Some(genre) = info.genres() {
+                manga.genre = genre;
+            }
+            if let Some(status) = info.status() {
+                manga.status = Some(status.to_string());
+            }
+            if let Some(summary) = info.summary() {
+                manga.description = Some(summary.to_string());
+            }
+        }
+
+        // details.json is written for tanoshi, it takes precedence over ComicInfo.xml
         if let Some(info) = find_details(&path)
             .and_then(|object| serde_json::from_slice::<LocalMangaInfo>(&object).ok())
         {
@@ -372,6 +916,9 @@ impl Extension for Local {
             if let Some(genre) = info.genre {
                 manga.genre = genre;
             }
+            if let Some(status) = info.status {
+                manga.status = Some(status);
+            }
             if let Some(description) = info.description {
                 manga.description = Some(description);
             }
@@ -383,26 +930,58 @@ impl Extension for Local {
     fn get_chapters(&self, path: String) -> Result<Vec<ChapterInfo>> {
         let source_id = self.id;
         let path = PathBuf::from(path);
+
+        let entries = self.index.entries().ok();
+        let indexed = entries
+            .iter()
+            .flat_map(|entries| entries.iter())
+            .find(|entry| Path::new(&entry.path) == path)
+            .map(|entry| &entry.comic_info);
+        // series outside of the index, or without one, are read as they are
+        let comic_info = |chapter: &Path| match indexed {
+            Some(comic_info) => comic_info.get(chapter.to_string_lossy().as_ref()).cloned(),
+            None => ComicInfo::read(chapter),
+        };
+
         if path.is_file() {
-            if let Some(data) = map_entry_to_chapter(source_id, &path) {
+            if let Some(data) = map_entry_to_chapter(source_id, &path, comic_info(&path)) {
                 return Ok(vec![data]);
             }
         }
 
-        let read_dir = match std::fs::read_dir(&path) {
-            Ok(read_dir) => read_dir,
-            Err(e) => {
-                return Err(anyhow!("{}", e));
-            }
-        };
+        if let Err(e) = std::fs::read_dir(&path) {
+            return Err(anyhow!("{}", e));
+        }
 
-        let mut data: Vec<ChapterInfo> = read_dir
+        let mut chapters = vec![];
+        find_chapters(&path, None, 1, self.max_depth, &self.filter, &mut chapters);
+        let mut data: Vec<ChapterInfo> = chapters
             .into_iter()
-            .filter_map(filter_supported_files_and_folders)
-            .filter_map(|entry| map_entry_to_chapter(source_id, &entry.path()))
+            .filter_map(|(chapter_path, volume)| {
+                let info = comic_info(&chapter_path);
+                let mut chapter = map_entry_to_chapter(source_id, &chapter_path, info)?;
+                if let Some(volume) = volume {
+                    if !chapter.title.starts_with("Vol.") {
+                        chapter.title = format!("Vol. {volume} {}", chapter.title);
+                    }
+                }
+                chapter.volume = chapter.volume.or(volume);
+                Some(chapter)
+            })
             .collect();
 
-        data.sort_by(|a, b| a.number.partial_cmp(&b.number).unwrap());
+        // chapters without volume are usually newer than any volume
+        data.sort_by(|a, b| {
+            a.volume
+                .unwrap_or(f64::MAX)
+                .partial_cmp(&b.volume.unwrap_or(f64::MAX))
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| {
+                    a.number
+                        .partial_cmp(&b.number)
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                })
+        });
         data.reverse();
 
         Ok(data)
@@ -410,13 +989,13 @@ impl Extension for Local {
 
     fn get_pages(&self, filename: String) -> Result<Vec<String>> {
         let path = PathBuf::from(filename);
-        let mut pages = if path.is_dir() {
-            match get_pages_from_dir(&path) {
+        let pages = if path.is_dir() {
+            match get_pages_from_dir(&path, &self.page_filter) {
                 Ok(pages) => pages,
                 Err(e) => return Err(anyhow!("{}", e)),
             }
         } else if path.is_file() {
-            match get_pages_from_archive(&path) {
+            match get_pages_from_archive(&path, &self.page_filter) {
                 Ok(pages) => pages,
                 Err(e) => return Err(anyhow!("{}", e)),
             }
@@ -424,8 +1003,6 @@ impl Extension for Local {
             return Err(anyhow!("filename neither file or dir"));
         };
 
-        pages.sort_by(|a, b| human_sort::compare(a, b));
-
         Ok(pages)
     }
 }
@@ -489,6 +1066,27 @@ mod test {
         assert_eq!(manga.unwrap().len(), 0);
     }
 
+    #[tokio::test]
+    async fn test_search_manga_with_filters() {
+        let local = Local::new(1, "Local".to_string(), "../../test/data/manga");
+        let filters = vec![
+            Input::Select {
+                name: FORMAT_FILTER.to_string(),
+                values: vec![],
+                state: Some(1),
+            },
+            Input::Sort {
+                name: SORT_FILTER.to_string(),
+                values: vec![],
+                selection: Some((0, false)),
+            },
+        ];
+        let manga = local.search_manga(1, None, Some(filters)).unwrap();
+
+        let titles: Vec<String> = manga.into_iter().map(|manga| manga.title).collect();
+        assert_eq!(titles, vec!["Super Duck", "Space Adventures"]);
+    }
+
     #[tokio::test]
     async fn test_get_manga_detail_single_archive() {
         let local = Local::new(1, "Local".to_string(), "../../test/data/manga");
@@ -877,4 +1475,161 @@ mod test {
             }
         }
     }
+
+    // fresh copy of fixtures, metadata tests write to it
+    fn temp_root(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("tanoshi-local-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    fn copy_dir(from: &Path, to: &Path) {
+        std::fs::create_dir_all(to).unwrap();
+        for entry in std::fs::read_dir(from).unwrap() {
+            let entry = entry.unwrap();
+            let path = entry.path();
+            if path.is_dir() {
+                copy_dir(&path, &to.join(entry.file_name()));
+            } else {
+                std::fs::copy(&path, to.join(entry.file_name())).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_write_manga_info_dir() {
+        let root = temp_root("dir");
+        let path = root.join("Super Duck");
+        copy_dir(Path::new("../../test/data/manga/Super Duck"), &path);
+
+        write_manga_info(
+            &root,
+            &path,
+            LocalMangaInfo {
+                title: Some("Duck".to_string()),
+                cover_path: Some("super_duck_1/duck02.jpg".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let local = Local::new(1, "Local".to_string(), &root);
+        let manga = local.get_manga_detail(path.display().to_string()).unwrap();
+        assert_eq!(manga.title, "Duck");
+        assert_eq!(
+            manga.cover_url,
+            path.join("super_duck_1/duck02.jpg").display().to_string()
+        );
+        // fields not written are kept
+        assert!(manga.description.unwrap().starts_with("Super Duck is"));
+
+        assert!(write_manga_info(
+            &root,
+            &path,
+            LocalMangaInfo {
+                cover_path: Some("../Super Duck/details.json".to_string()),
+                ..Default::default()
+            },
+        )
+        .is_err());
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_write_manga_info_cbz() {
+        use std::io::Write;
+
+        let root = temp_root("cbz");
+        let path = root.join("Super Duck.cbz");
+        {
+            let fixture = Path::new("../../test/data/manga/Super Duck");
+            let options = zip::write::FileOptions::default()
+                .compression_method(zip::CompressionMethod::Stored);
+            let mut writer = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+            for page in ["duck00.jpg", "duck01.jpg"] {
+                writer.start_file(page, options).unwrap();
+                writer
+                    .write_all(&std::fs::read(fixture.join("super_duck_1").join(page)).unwrap())
+                    .unwrap();
+            }
+            writer.start_file("details.json", options).unwrap();
+            writer
+                .write_all(&std::fs::read(fixture.join("details.json")).unwrap())
+                .unwrap();
+            writer.finish().unwrap();
+        }
+
+        write_manga_info(
+            &root,
+            &path,
+            LocalMangaInfo {
+                title: Some("Duck".to_string()),
+                description: Some("Quack".to_string()),
+                cover_path: Some("duck01.jpg".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let local = Local::new(1, "Local".to_string(), &root);
+        let manga = local.get_manga_detail(path.display().to_string()).unwrap();
+        assert_eq!(manga.title, "Duck");
+        assert_eq!(manga.description.as_deref(), Some("Quack"));
+        assert_eq!(
+            manga.cover_url,
+            path.join("duck01.jpg").display().to_string()
+        );
+        assert!(comicinfo::read_xml(&path)
+            .unwrap()
+            .contains("<Series>Duck</Series>"));
+        assert_eq!(
+            get_pages_from_archive(&path, &PageFilter::default())
+                .unwrap()
+                .len(),
+            2
+        );
+
+        assert!(write_manga_info(
+            &root,
+            &path,
+            LocalMangaInfo {
+                cover_path: Some("missing.jpg".to_string()),
+                ..Default::default()
+            },
+        )
+        .is_err());
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_supported_file_upper_case_extension() {
+        let root = temp_root("extension");
+        std::fs::create_dir_all(root.join("Volume 1")).unwrap();
+        for name in ["Chapter.PDF", "Vol1.CBZ", "Book.Epub", "notes.TXT"] {
+            std::fs::write(root.join(name), b"").unwrap();
+        }
+
+        assert!(is_supported_file(&root.join("Chapter.PDF")));
+        assert!(is_supported_file(&root.join("Vol1.CBZ")));
+        assert!(is_supported_file(&root.join("Book.Epub")));
+        assert!(!is_supported_file(&root.join("notes.TXT")));
+
+        let mut entries: Vec<String> = root
+            .read_dir()
+            .unwrap()
+            .filter_map(filter_supported_files_and_folders)
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect();
+        entries.sort();
+        assert_eq!(
+            entries,
+            vec!["Book.Epub", "Chapter.PDF", "Vol1.CBZ", "Volume 1"]
+        );
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
 }
@@ -0,0 +1,142 @@
+use fancy_regex::Regex;
+use once_cell::sync::Lazy;
+
+// `v02c015` is common, so a volume may be followed by `c`
+static VOLUME_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)(?<![a-z\d])(?:volume|vol|tome|v)\.?\s*(\d+(?:\.\d+)?)(?!\d|[a-bd-z])")
+        .unwrap()
+});
+
+// `c010v2` marks a second release of chapter 10, so a chapter may be followed by `v`
+static CHAPTER_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)(?<![a-z])(?:chapter|chap|ch|c|episode|ep|#)\.?\s*(\d+(?:\.\d+)?)(?:\s*-\s*(?:(?:chapter|chap|ch|c)\.?\s*)?(\d+(?:\.\d+)?))?(?!\d|[a-uw-z])",
+    )
+    .unwrap()
+});
+
+static NUMBER_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?<![\w.])(\d+(?:\.\d+)?)(?:\s*-\s*(\d+(?:\.\d+)?))?(?!\w)").unwrap()
+});
+
+// group, year or quality tags
+static TAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\[[^\]]*\]|\([^)]*\)|\{[^}]*\}").unwrap());
+
+static EXTRA_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)(?<![a-z])(?:extras?|omake|bonus|special|side[ -]?story)(?![a-z])").unwrap()
+});
+
+/// Volume, chapter and extra marker parsed from a chapter file name
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ChapterName {
+    pub volume: Option<f64>,
+    pub number: Option<f64>,
+    /// Last chapter of a range like `c010-011`
+    pub number_end: Option<f64>,
+    pub extra: bool,
+}
+
+fn parse_number(re: &Regex, name: &str) -> Option<(f64, Option<f64>)> {
+    let captures = re.captures(name).ok()??;
+    let number: f64 = captures.get(1)?.as_str().parse().ok()?;
+    let number_end = captures
+        .get(2)
+        .and_then(|end| end.as_str().parse().ok())
+        .filter(|end| *end > number);
+
+    Some((number, number_end))
+}
+
+impl ChapterName {
+    /// Explicit volume and chapter markers are used first, otherwise the first number
+    /// outside of brackets is the chapter.
+    pub fn parse(name: &str) -> Self {
+        let name = name.replace('_', " ");
+
+        let volume = parse_number(&VOLUME_RE, &name).map(|(volume, _)| volume);
+        let chapter = parse_number(&CHAPTER_RE, &name).or_else(|| {
+            let name = TAG_RE.replace_all(&name, " ");
+            let name = VOLUME_RE.replace_all(&name, " ");
+            parse_number(&NUMBER_RE, &name)
+        });
+
+        Self {
+            volume,
+            number: chapter.map(|(number, _)| number),
+            number_end: chapter.and_then(|(_, number_end)| number_end),
+            extra: EXTRA_RE.is_match(&name).unwrap_or(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_chapter_name() {
+        let cases = [
+            ("Vol 2 Ch 15.5", Some(2.0), Some(15.5), None, false),
+            (
+                "Vol.02 Ch.015.5 - The Return",
+                Some(2.0),
+                Some(15.5),
+                None,
+                false,
+            ),
+            ("v02c015", Some(2.0), Some(15.0), None, false),
+            (
+                "[Group] Series Name - c010-011 (v02) [Digital]",
+                Some(2.0),
+                Some(10.0),
+                Some(11.0),
+                false,
+            ),
+            (
+                "Series Name Ch. 10 - 9 Lives",
+                None,
+                Some(10.0),
+                None,
+                false,
+            ),
+            ("Series Name c010v2", None, Some(10.0), None, false),
+            ("Series Name 045", None, Some(45.0), None, false),
+            (
+                "Series Name 045 (2019) [1080p]",
+                None,
+                Some(45.0),
+                None,
+                false,
+            ),
+            ("Series Name - 012.5", None, Some(12.5), None, false),
+            ("Series Name v01 045", Some(1.0), Some(45.0), None, false),
+            ("20th Century Boys 015", None, Some(15.0), None, false),
+            (
+                "Space_Adventures_004__c2c__diff_ver",
+                None,
+                Some(4.0),
+                None,
+                false,
+            ),
+            ("Series Name #3", None, Some(3.0), None, false),
+            ("Episode 12", None, Some(12.0), None, false),
+            ("Chapter 7 Omake", None, Some(7.0), None, true),
+            ("Series Name - Side Story", None, None, None, true),
+            ("Series Name Vol 03", Some(3.0), None, None, false),
+            ("Oneshot", None, None, None, false),
+        ];
+
+        for (name, volume, number, number_end, extra) in cases {
+            assert_eq!(
+                ChapterName::parse(name),
+                ChapterName {
+                    volume,
+                    number,
+                    number_end,
+                    extra
+                },
+                "{name}"
+            );
+        }
+    }
+}
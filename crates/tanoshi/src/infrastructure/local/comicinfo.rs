@@ -0,0 +1,240 @@
+use std::path::Path;
+
+use chrono::NaiveDate;
+use fancy_regex::Regex;
+use serde::{Deserialize, Serialize};
+
+pub const FILE_NAME: &str = "ComicInfo.xml";
+
+/// Subset of ComicRack `ComicInfo.xml`, numbers are kept as string since
+/// taggers often write them empty or with extra characters
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename = "ComicInfo", default, rename_all = "PascalCase")]
+pub struct ComicInfo {
+    pub title: Option<String>,
+    pub series: Option<String>,
+    pub number: Option<String>,
+    pub volume: Option<String>,
+    pub summary: Option<String>,
+    pub writer: Option<String>,
+    pub genre: Option<String>,
+    pub status: Option<String>,
+    pub scan_information: Option<String>,
+    pub year: Option<String>,
+    pub month: Option<String>,
+    pub day: Option<String>,
+    pub page_count: Option<String>,
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value
+        .as_deref()
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty())
+}
+
+fn split_list(value: &Option<String>) -> Option<Vec<String>> {
+    non_empty(value).map(|value| {
+        value
+            .split(',')
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect()
+    })
+}
+
+/// Raw `ComicInfo.xml` of an archive or a directory
+pub fn read_xml(path: &Path) -> Option<String> {
+    let data = if path.is_dir() {
+        std::fs::read(path.join(FILE_NAME)).ok()?
+    } else {
+        let source = std::fs::File::open(path).ok()?;
+        let mut data = vec![];
+        compress_tools::uncompress_archive_file(source, &mut data, FILE_NAME).ok()?;
+        data
+    };
+
+    Some(String::from_utf8_lossy(&data).to_string())
+}
+
+pub fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Set element `name` of a `ComicInfo.xml` document, other elements are kept as is
+pub fn set_element(xml: &str, name: &str, value: &str) -> String {
+    let element = format!("<{name}>{}</{name}>", escape(value));
+    let existing = Regex::new(&format!(r"(?s)<{name}\s*/>|<{name}>.*?</{name}>"))
+        .ok()
+        .and_then(|re| re.find(xml).ok().flatten())
+        .map(|m| m.range());
+
+    match existing {
+        Some(range) => format!("{}{element}{}", &xml[..range.start], &xml[range.end..]),
+        None => match xml.rfind("</ComicInfo>") {
+            Some(end) => format!("{}  {element}\n{}", &xml[..end], &xml[end..]),
+            None => format!("<?xml version=\"1.0\"?>\n<ComicInfo>\n  {element}\n</ComicInfo>\n"),
+        },
+    }
+}
+
+/// New `ComicInfo.xml` document with `elements` in order, empty values are left out
+pub fn build_xml(elements: &[(&str, String)]) -> String {
+    elements
+        .iter()
+        .filter(|(_, value)| !value.trim().is_empty())
+        .fold(
+            "<?xml version=\"1.0\"?>\n<ComicInfo>\n</ComicInfo>\n".to_string(),
+            |xml, (name, value)| set_element(&xml, name, value),
+        )
+}
+
+impl ComicInfo {
+    pub fn parse(xml: &str) -> Option<Self> {
+        match quick_xml::de::from_str(xml) {
+            Ok(info) => Some(info),
+            Err(e) => {
+                debug!("invalid {FILE_NAME}: {e}");
+                None
+            }
+        }
+    }
+
+    /// Read `ComicInfo.xml` from an archive or a directory
+    pub fn read(path: &Path) -> Option<Self> {
+        Self::parse(&read_xml(path)?)
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        non_empty(&self.title)
+    }
+
+    pub fn series(&self) -> Option<&str> {
+        non_empty(&self.series)
+    }
+
+    pub fn summary(&self) -> Option<&str> {
+        non_empty(&self.summary)
+    }
+
+    pub fn status(&self) -> Option<&str> {
+        non_empty(&self.status)
+    }
+
+    pub fn scanlator(&self) -> Option<&str> {
+        non_empty(&self.scan_information)
+    }
+
+    pub fn page_count(&self) -> Option<usize> {
+        non_empty(&self.page_count).and_then(|count| count.parse().ok())
+    }
+
+    pub fn volume(&self) -> Option<&str> {
+        non_empty(&self.volume)
+    }
+
+    pub fn number(&self) -> Option<f64> {
+        non_empty(&self.number).and_then(|number| number.parse().ok())
+    }
+
+    pub fn authors(&self) -> Option<Vec<String>> {
+        split_list(&self.writer)
+    }
+
+    pub fn genres(&self) -> Option<Vec<String>> {
+        split_list(&self.genre)
+    }
+
+    /// Unix timestamp of Year/Month/Day, month and day default to 1
+    pub fn uploaded(&self) -> Option<i64> {
+        let year = non_empty(&self.year)?.parse().ok()?;
+        let month = non_empty(&self.month)
+            .and_then(|month| month.parse().ok())
+            .unwrap_or(1);
+        let day = non_empty(&self.day)
+            .and_then(|day| day.parse().ok())
+            .unwrap_or(1);
+
+        NaiveDate::from_ymd_opt(year, month, day)
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+            .map(|date| date.timestamp())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_comic_info() {
+        let info = ComicInfo::parse(
+            r#"<?xml version="1.0"?>
+<ComicInfo xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+  <Title>The Beginning</Title>
+  <Series>Space Adventures</Series>
+  <Number>4.5</Number>
+  <Volume>1</Volume>
+  <Summary>Rocket &amp; friends</Summary>
+  <Writer>Jane Doe, John Doe</Writer>
+  <Genre>Sci-Fi,Adventure</Genre>
+  <ScanInformation>Scans Inc</ScanInformation>
+  <Year>1952</Year>
+  <Month>3</Month>
+  <Day></Day>
+</ComicInfo>"#,
+        )
+        .unwrap();
+
+        assert_eq!(info.title(), Some("The Beginning"));
+        assert_eq!(info.series(), Some("Space Adventures"));
+        assert_eq!(info.number(), Some(4.5));
+        assert_eq!(info.volume(), Some("1"));
+        assert_eq!(info.summary(), Some("Rocket & friends"));
+        assert_eq!(
+            info.authors(),
+            Some(vec!["Jane Doe".to_string(), "John Doe".to_string()])
+        );
+        assert_eq!(
+            info.genres(),
+            Some(vec!["Sci-Fi".to_string(), "Adventure".to_string()])
+        );
+        assert_eq!(info.scanlator(), Some("Scans Inc"));
+        assert_eq!(info.status(), None);
+        assert_eq!(info.uploaded(), Some(-562896000));
+    }
+
+    #[test]
+    fn test_set_element() {
+        let xml =
+            "<ComicInfo>\n  <Series>Old</Series>\n  <Penciller>Someone</Penciller>\n</ComicInfo>";
+
+        let xml = set_element(xml, "Series", "New & Improved");
+        let xml = set_element(&xml, "Summary", "Hello");
+        assert_eq!(
+            xml,
+            "<ComicInfo>\n  <Series>New &amp; Improved</Series>\n  <Penciller>Someone</Penciller>\n  <Summary>Hello</Summary>\n</ComicInfo>"
+        );
+
+        let info = ComicInfo::parse(&xml).unwrap();
+        assert_eq!(info.series(), Some("New & Improved"));
+        assert_eq!(info.summary(), Some("Hello"));
+    }
+
+    #[test]
+    fn test_build_xml() {
+        let xml = build_xml(&[
+            ("Series", "Space \"Adventures\"".to_string()),
+            ("Number", "1.5".to_string()),
+            ("Summary", "".to_string()),
+        ]);
+
+        let info = ComicInfo::parse(&xml).unwrap();
+        assert_eq!(info.series(), Some("Space \"Adventures\""));
+        assert_eq!(info.number(), Some(1.5));
+        assert_eq!(info.summary(), None);
+    }
+}
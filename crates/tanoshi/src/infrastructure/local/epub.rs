@@ -0,0 +1,259 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{anyhow, Result};
+use compress_tools::{ArchiveContents, ArchiveIterator};
+use itertools::Itertools;
+use quick_xml::{events::Event, Reader};
+
+use super::is_image;
+
+/// Every entry of the archive that is not an image, read in a single pass
+fn read_documents(path: &Path) -> Result<HashMap<String, String>> {
+    let mut documents = HashMap::new();
+    let mut entry: Option<(String, Vec<u8>)> = None;
+    for content in ArchiveIterator::from_read(std::fs::File::open(path)?)? {
+        match content {
+            ArchiveContents::StartOfEntry(name, ..) => {
+                entry = if is_image(Path::new(&name)) {
+                    None
+                } else {
+                    Some((name, vec![]))
+                };
+            }
+            ArchiveContents::DataChunk(chunk) => {
+                if let Some((_, data)) = entry.as_mut() {
+                    data.extend_from_slice(&chunk);
+                }
+            }
+            ArchiveContents::EndOfEntry => {
+                if let Some((name, data)) = entry.take() {
+                    documents.insert(name, String::from_utf8_lossy(&data).to_string());
+                }
+            }
+            ArchiveContents::Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(documents)
+}
+
+fn read_entry<'a>(
+    documents: &'a HashMap<String, String>,
+    path: &Path,
+    name: &str,
+) -> Result<&'a str> {
+    documents
+        .get(name)
+        .map(String::as_str)
+        .ok_or_else(|| anyhow!("{} has no {name}", path.display()))
+}
+
+/// Strip namespace prefix, e.g. `opf:item` or `xlink:href`
+fn local_name(name: &[u8]) -> String {
+    let name = String::from_utf8_lossy(name);
+    name.rsplit(':').next().unwrap_or_default().to_lowercase()
+}
+
+/// Attributes of every element named one of `names` in `content` in document order, keyed by
+/// lowercase local name. Parsing stops at the first error, xhtml in the wild is not always
+/// well formed
+fn tags(content: &str, names: &[&str]) -> Vec<HashMap<String, String>> {
+    let mut reader = Reader::from_str(content);
+    reader.check_end_names(false);
+
+    let mut tags = vec![];
+    let mut buf = vec![];
+    loop {
+        match reader.read_event(&mut buf) {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e))
+                if names.contains(&local_name(e.name()).as_str()) =>
+            {
+                tags.push(
+                    e.attributes()
+                        .with_checks(false)
+                        .filter_map(|attr| attr.ok())
+                        .map(|attr| {
+                            let value = attr
+                                .unescaped_value()
+                                .map(|value| String::from_utf8_lossy(&value).to_string())
+                                .unwrap_or_else(|_| {
+                                    String::from_utf8_lossy(&attr.value).to_string()
+                                });
+                            (local_name(attr.key), value)
+                        })
+                        .collect(),
+                );
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => {
+                debug!("stop parsing at {}: {e}", reader.buffer_position());
+                break;
+            }
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    tags
+}
+
+fn attr(tag: &HashMap<String, String>, name: &str) -> Option<String> {
+    tag.get(name).cloned()
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(b) = s
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                decoded.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Resolve `href` relative to the archive entry `base`
+fn resolve_href(base: &str, href: &str) -> String {
+    let href = percent_decode(href.split('#').next().unwrap_or_default());
+
+    let mut components: Vec<&str> = base.split('/').collect();
+    // last component of base is the file itself
+    components.pop();
+    for component in href.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+
+    components.join("/")
+}
+
+struct Item {
+    id: String,
+    href: String,
+    media_type: String,
+}
+
+/// Images of an epub in spine order, each spine document contributes the images it references
+pub fn get_pages(path: &Path) -> Result<Vec<String>> {
+    let documents = read_documents(path)?;
+    let container = read_entry(&documents, path, "META-INF/container.xml")?;
+    let rootfile = tags(container, &["rootfile"])
+        .iter()
+        .find_map(|tag| attr(tag, "full-path"))
+        .ok_or_else(|| anyhow!("{} has no rootfile", path.display()))?;
+
+    let package = read_entry(&documents, path, &rootfile)?;
+    let manifest: Vec<Item> = tags(package, &["item"])
+        .iter()
+        .filter_map(|tag| {
+            Some(Item {
+                id: attr(tag, "id")?,
+                href: resolve_href(&rootfile, &attr(tag, "href")?),
+                media_type: attr(tag, "media-type").unwrap_or_default(),
+            })
+        })
+        .collect();
+
+    let mut pages = vec![];
+    for idref in tags(package, &["itemref"])
+        .iter()
+        .filter_map(|tag| attr(tag, "idref"))
+    {
+        let item = match manifest.iter().find(|item| item.id == idref) {
+            Some(item) => item,
+            None => continue,
+        };
+
+        if item.media_type.starts_with("image/") {
+            pages.push(item.href.clone());
+            continue;
+        }
+
+        let document = match read_entry(&documents, path, &item.href) {
+            Ok(document) => document,
+            Err(e) => {
+                warn!("failed to read {}: {e}", item.href);
+                continue;
+            }
+        };
+        for tag in tags(document, &["img", "image"]) {
+            // `href` also matches `xlink:href` used by svg image
+            if let Some(src) = attr(&tag, "src").or_else(|| attr(&tag, "href")) {
+                pages.push(resolve_href(&item.href, &src));
+            }
+        }
+    }
+
+    Ok(pages
+        .into_iter()
+        .unique()
+        .map(|page| path.join(page).display().to_string())
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resolve_href() {
+        assert_eq!(
+            resolve_href("OEBPS/content.opf", "images/p001.jpg"),
+            "OEBPS/images/p001.jpg"
+        );
+        assert_eq!(
+            resolve_href("OEBPS/text/p001.xhtml", "../images/p%20001.jpg#frag"),
+            "OEBPS/images/p 001.jpg"
+        );
+        assert_eq!(resolve_href("content.opf", "./p001.jpg"), "p001.jpg");
+    }
+
+    #[test]
+    fn test_tags() {
+        let package = r#"<?xml version="1.0"?>
+<opf:package xmlns:opf="http://www.idpf.org/2007/opf">
+  <opf:manifest>
+    <opf:item id="p1" href="text/p001.xhtml" media-type="application/xhtml+xml"/>
+    <opf:item id="img" href="images/a&amp;b.jpg" media-type="image/jpeg"></opf:item>
+  </opf:manifest>
+</opf:package>"#;
+        let items = tags(package, &["item"]);
+        assert_eq!(items.len(), 2);
+        assert_eq!(attr(&items[0], "id").as_deref(), Some("p1"));
+        assert_eq!(attr(&items[1], "href").as_deref(), Some("images/a&b.jpg"));
+
+        // unclosed html tags and svg images, in document order
+        let document = r#"<html><body><p><img src="../images/p001.jpg"><br>
+<svg><image xlink:href="../images/p002.jpg"/></svg>
+<img src="../images/p003.jpg"></body></html>"#;
+        let images = tags(document, &["img", "image"]);
+        assert_eq!(images.len(), 3);
+        assert_eq!(
+            attr(&images[0], "src").as_deref(),
+            Some("../images/p001.jpg")
+        );
+        assert_eq!(
+            attr(&images[1], "href").as_deref(),
+            Some("../images/p002.jpg")
+        );
+        assert_eq!(
+            attr(&images[2], "src").as_deref(),
+            Some("../images/p003.jpg")
+        );
+    }
+}
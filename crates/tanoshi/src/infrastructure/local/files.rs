@@ -0,0 +1,158 @@
+use std::{cmp::Ordering, path::Path};
+
+use anyhow::Result;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+/// Which files and folders of a local folder are read as series and chapters
+#[derive(Debug, Clone, Default)]
+pub struct FileFilter {
+    include: Option<GlobSet>,
+    exclude: Option<GlobSet>,
+    hidden: bool,
+}
+
+fn glob_set(patterns: &[String]) -> Result<Option<GlobSet>> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+
+    Ok(Some(builder.build()?))
+}
+
+impl FileFilter {
+    /// Patterns are matched against file and folder names, `include` only applies to files
+    /// so folders are still walked
+    pub fn new(include: &[String], exclude: &[String], hidden: bool) -> Result<Self> {
+        Ok(Self {
+            include: glob_set(include)?,
+            exclude: glob_set(exclude)?,
+            hidden,
+        })
+    }
+
+    pub fn allows(&self, path: &Path) -> bool {
+        let name = match path.file_name() {
+            Some(name) => name,
+            None => return true,
+        };
+
+        if !self.hidden && name.to_string_lossy().starts_with('.') {
+            return false;
+        }
+        if let Some(exclude) = self.exclude.as_ref() {
+            if exclude.is_match(name) {
+                return false;
+            }
+        }
+        match self.include.as_ref() {
+            Some(include) if path.is_file() => include.is_match(name),
+            _ => true,
+        }
+    }
+}
+
+/// Images inside a chapter that are not pages, like thumbnails or credit pages
+#[derive(Debug, Clone, Default)]
+pub struct PageFilter {
+    exclude: Option<GlobSet>,
+}
+
+fn is_page_path_component(component: &str) -> bool {
+    !component.starts_with('.') && component != "__MACOSX"
+}
+
+impl PageFilter {
+    /// Patterns are matched against page path inside the chapter, e.g. `**/credits*`
+    pub fn new(exclude: &[String]) -> Result<Self> {
+        Ok(Self {
+            exclude: glob_set(exclude)?,
+        })
+    }
+
+    /// `path` is relative to the chapter, `__MACOSX` and hidden files are always skipped
+    pub fn allows(&self, path: &str) -> bool {
+        path.split(|c| c == '/' || c == '\\')
+            .all(is_page_path_component)
+            && !self
+                .exclude
+                .as_ref()
+                .map(|exclude| exclude.is_match(path))
+                .unwrap_or(false)
+    }
+}
+
+/// Natural order of page paths, compared by folder so `2/01.jpg` comes before `10/01.jpg`
+pub fn compare_pages(a: &str, b: &str) -> Ordering {
+    let mut a = a.split(|c| c == '/' || c == '\\');
+    let mut b = b.split(|c| c == '/' || c == '\\');
+    loop {
+        match (a.next(), b.next()) {
+            (Some(a), Some(b)) => match human_sort::compare(&a.to_lowercase(), &b.to_lowercase()) {
+                Ordering::Equal => continue,
+                ordering => return ordering,
+            },
+            (a, b) => return a.is_some().cmp(&b.is_some()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_file_filter() {
+        let filter =
+            FileFilter::new(&["*.cbz".to_string()], &["Super*".to_string()], false).unwrap();
+
+        assert!(filter.allows(Path::new("../../test/data/manga/Space Adventures")));
+        assert!(!filter.allows(Path::new("../../test/data/manga/Super Duck")));
+        assert!(!filter.allows(Path::new(
+            "../../test/data/manga/this_should_be_filtered.txt"
+        )));
+        assert!(!filter.allows(Path::new("../../test/data/manga/.thumbnails")));
+        assert!(FileFilter::new(&[], &[], true)
+            .unwrap()
+            .allows(Path::new("../../test/data/manga/.thumbnails")));
+    }
+
+    #[test]
+    fn test_page_filter() {
+        let filter = PageFilter::new(&["**/credits*".to_string()]).unwrap();
+
+        assert!(filter.allows("chapter 1/001.jpg"));
+        assert!(!filter.allows("__MACOSX/chapter 1/._001.jpg"));
+        assert!(!filter.allows("chapter 1/.thumb.jpg"));
+        assert!(!filter.allows("chapter 1/credits.png"));
+    }
+
+    #[test]
+    fn test_compare_pages() {
+        let mut pages = vec![
+            "10/01.jpg",
+            "2/10.jpg",
+            "2/9.jpg",
+            "Extra/01.jpg",
+            "1.jpg",
+            "2/Page 1.jpg",
+        ];
+        pages.sort_by(|a, b| compare_pages(a, b));
+
+        assert_eq!(
+            pages,
+            vec![
+                "1.jpg",
+                "2/9.jpg",
+                "2/10.jpg",
+                "2/Page 1.jpg",
+                "10/01.jpg",
+                "Extra/01.jpg",
+            ]
+        );
+    }
+}
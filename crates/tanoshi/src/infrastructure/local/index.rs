@@ -0,0 +1,295 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    comicinfo::ComicInfo, files::FileFilter, find_cover_url, find_genres, find_series,
+    find_series_chapters, find_series_in,
+};
+
+/// A series found in a local folder
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub path: String,
+    pub title: String,
+    pub cover_url: String,
+    pub genres: Vec<String>,
+    pub chapters: usize,
+    /// `ComicInfo.xml` of chapters by their path, archives are only opened when indexing
+    pub comic_info: HashMap<String, ComicInfo>,
+    /// Latest modification time in seconds since epoch of the series and directories inside it,
+    /// an entry is only rebuilt when it changes
+    pub modified: u64,
+}
+
+/// Series of a local folder, built on first use and kept on disk between restarts
+pub struct LocalIndex {
+    root: PathBuf,
+    max_depth: usize,
+    filter: FileFilter,
+    cache_path: Option<PathBuf>,
+    entries: RwLock<Option<Arc<Vec<IndexEntry>>>>,
+}
+
+fn mtime(path: &Path) -> u64 {
+    path.metadata()
+        .ok()
+        .and_then(|metadata| metadata.modified().ok())
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|modified| modified.as_secs())
+        .unwrap_or(0)
+}
+
+// adding, removing or renaming a chapter changes the directory containing it, so only the
+// directories of a series are checked, edits of files are picked up by the watcher
+fn modified(path: &Path, depth: usize) -> u64 {
+    let own = mtime(path);
+    if depth == 0 {
+        return own;
+    }
+
+    match path.read_dir() {
+        Ok(dir) => dir
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().map(|t| t.is_dir()).unwrap_or(false))
+            .map(|entry| modified(&entry.path(), depth - 1))
+            .fold(own, u64::max),
+        Err(_) => own,
+    }
+}
+
+// latest modification time of directories containing `changed` paths inside series at `path`
+fn changed_modified(path: &Path, changed: &[&PathBuf]) -> u64 {
+    changed
+        .iter()
+        .map(|changed| {
+            if changed.as_path() == path || changed.is_dir() {
+                mtime(changed)
+            } else {
+                changed.parent().map(mtime).unwrap_or(0)
+            }
+        })
+        .fold(0, u64::max)
+}
+
+impl LocalIndex {
+    pub fn new<P: AsRef<Path>>(
+        root: P,
+        max_depth: usize,
+        filter: FileFilter,
+        cache_path: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+            max_depth,
+            filter,
+            cache_path,
+            entries: RwLock::new(None),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn read_cache(&self) -> HashMap<String, IndexEntry> {
+        self.cache_path
+            .as_ref()
+            .and_then(|path| std::fs::read(path).ok())
+            .and_then(|data| bincode::deserialize::<Vec<IndexEntry>>(&data).ok())
+            .map(|entries| {
+                entries
+                    .into_iter()
+                    .map(|entry| (entry.path.clone(), entry))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn write_cache(&self, entries: &[IndexEntry]) -> Result<()> {
+        if let Some(path) = self.cache_path.as_ref() {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(path, bincode::serialize(entries)?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Walk the folder again, only series with changed modification time are rebuilt
+    pub fn build(&self) -> Result<()> {
+        // fail instead of emptying the index when the folder is gone
+        std::fs::read_dir(&self.root)?;
+
+        let mut cached = match self.entries.read() {
+            Ok(entries) => match entries.as_ref() {
+                Some(entries) => entries
+                    .iter()
+                    .map(|entry| (entry.path.clone(), entry.clone()))
+                    .collect(),
+                None => self.read_cache(),
+            },
+            Err(_) => self.read_cache(),
+        };
+
+        let mut series = vec![];
+        find_series(&self.root, 1, self.max_depth, &self.filter, &mut series);
+
+        let entries: Vec<IndexEntry> = series
+            .into_iter()
+            .map(|path| {
+                let modified = modified(&path, self.max_depth);
+                match cached.remove(path.to_string_lossy().as_ref()) {
+                    Some(entry) if entry.modified == modified => entry,
+                    _ => self.entry(path, modified),
+                }
+            })
+            .collect();
+
+        self.store(entries)
+    }
+
+    /// Rebuild only series affected by `paths`. Series are looked up again under the top level
+    /// directory of each changed path, which is where series can appear or disappear
+    pub fn update(&self, paths: &[PathBuf]) -> Result<()> {
+        let current = self
+            .entries
+            .read()
+            .map_err(|e| anyhow!("failed to lock index: {e}"))?
+            .clone();
+        let mut entries = match current {
+            Some(entries) => entries.as_ref().clone(),
+            None => return self.build(),
+        };
+
+        let mut tops: Vec<PathBuf> = paths.iter().filter_map(|p| self.top_level(p)).collect();
+        tops.sort();
+        tops.dedup();
+
+        for top in tops {
+            let position = entries
+                .iter()
+                .position(|entry| Path::new(&entry.path).starts_with(&top));
+            let (removed, kept): (Vec<IndexEntry>, Vec<IndexEntry>) = entries
+                .into_iter()
+                .partition(|entry| Path::new(&entry.path).starts_with(&top));
+            entries = kept;
+
+            let mut cached: HashMap<String, IndexEntry> = removed
+                .into_iter()
+                .map(|entry| (entry.path.clone(), entry))
+                .collect();
+
+            let mut series = vec![];
+            find_series_in(&top, 1, self.max_depth, &self.filter, &mut series);
+            let updated: Vec<IndexEntry> = series
+                .into_iter()
+                .map(|path| {
+                    let changed: Vec<&PathBuf> =
+                        paths.iter().filter(|p| p.starts_with(&path)).collect();
+                    let replaced = paths.iter().any(|p| path.starts_with(p) && *p != path);
+                    match cached.remove(path.to_string_lossy().as_ref()) {
+                        // series not touched by the changes are kept without walking them
+                        Some(entry) if !replaced && changed.is_empty() => entry,
+                        // modification time has a resolution of seconds, so changed series are
+                        // always rebuilt
+                        Some(entry) if !replaced => {
+                            let modified = changed_modified(&path, &changed).max(entry.modified);
+                            self.entry(path, modified)
+                        }
+                        // a new series, or an ancestor was created, removed or renamed and the
+                        // series may be another one now
+                        _ => {
+                            let modified = modified(&path, self.max_depth);
+                            self.entry(path, modified)
+                        }
+                    }
+                })
+                .collect();
+
+            // top level directories are in the order `find_series` walks them
+            let position = position.unwrap_or_else(|| {
+                entries
+                    .iter()
+                    .position(|entry| {
+                        self.top_level(Path::new(&entry.path))
+                            .map(|entry_top| {
+                                human_sort::compare(
+                                    &entry_top.display().to_string(),
+                                    &top.display().to_string(),
+                                ) == Ordering::Greater
+                            })
+                            .unwrap_or(false)
+                    })
+                    .unwrap_or(entries.len())
+            });
+            entries.splice(position..position, updated);
+        }
+
+        self.store(entries)
+    }
+
+    fn top_level(&self, path: &Path) -> Option<PathBuf> {
+        let component = path.strip_prefix(&self.root).ok()?.components().next()?;
+        Some(self.root.join(component))
+    }
+
+    /// Entry of series at `path`, built from its files
+    fn entry(&self, path: PathBuf, modified: u64) -> IndexEntry {
+        let chapters = find_series_chapters(&path, self.max_depth, &self.filter);
+        IndexEntry {
+            title: path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            cover_url: find_cover_url(&path),
+            genres: find_genres(&path),
+            chapters: chapters.len(),
+            comic_info: chapters
+                .iter()
+                .filter_map(|(chapter, _)| {
+                    ComicInfo::read(chapter)
+                        .map(|info| (chapter.to_string_lossy().to_string(), info))
+                })
+                .collect(),
+            path: path.to_string_lossy().to_string(),
+            modified,
+        }
+    }
+
+    fn store(&self, entries: Vec<IndexEntry>) -> Result<()> {
+        if let Err(e) = self.write_cache(&entries) {
+            error!("failed to write index of {}: {e}", self.root.display());
+        }
+
+        *self
+            .entries
+            .write()
+            .map_err(|e| anyhow!("failed to lock index: {e}"))? = Some(Arc::new(entries));
+
+        Ok(())
+    }
+
+    /// Series in the order found, built on first call
+    pub fn entries(&self) -> Result<Arc<Vec<IndexEntry>>> {
+        if let Some(entries) = self
+            .entries
+            .read()
+            .map_err(|e| anyhow!("failed to lock index: {e}"))?
+            .as_ref()
+        {
+            return Ok(entries.clone());
+        }
+
+        self.build()?;
+        self.entries()
+    }
+}
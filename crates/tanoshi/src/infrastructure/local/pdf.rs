@@ -0,0 +1,243 @@
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, Result};
+use lopdf::{Dictionary, Document, Object, Stream};
+use once_cell::sync::Lazy;
+
+// pages of a chapter are requested one after another, so a few recently parsed
+// documents are kept instead of parsing the whole file for every page
+const CACHED_DOCUMENTS: usize = 4;
+
+type CachedDocument = (PathBuf, Option<SystemTime>, Arc<Document>);
+
+static DOCUMENTS: Lazy<Mutex<Vec<CachedDocument>>> = Lazy::new(|| Mutex::new(vec![]));
+
+fn load(path: &Path) -> Result<Arc<Document>> {
+    let modified = std::fs::metadata(path)?.modified().ok();
+    {
+        let mut documents = DOCUMENTS.lock().map_err(|e| anyhow!("{e}"))?;
+        if let Some(index) = documents
+            .iter()
+            .position(|(p, m, _)| p == path && modified.is_some() && *m == modified)
+        {
+            let cached = documents.remove(index);
+            let doc = cached.2.clone();
+            documents.insert(0, cached);
+            return Ok(doc);
+        }
+    }
+
+    let doc = Arc::new(Document::load(path)?);
+    let mut documents = DOCUMENTS.lock().map_err(|e| anyhow!("{e}"))?;
+    documents.retain(|(p, _, _)| p != path);
+    documents.insert(0, (path.to_path_buf(), modified, doc.clone()));
+    documents.truncate(CACHED_DOCUMENTS);
+
+    Ok(doc)
+}
+
+// pages are served as their largest embedded image, which is how scanned and
+// fixed layout comics are usually stored
+pub fn get_pages(path: &Path) -> Result<Vec<String>> {
+    let doc = load(path)?;
+    Ok(doc
+        .get_pages()
+        .keys()
+        .map(|page| path.join(page.to_string()).display().to_string())
+        .collect())
+}
+
+fn resolve<'a>(doc: &'a Document, object: &'a Object) -> Result<&'a Object> {
+    match object {
+        Object::Reference(id) => Ok(doc.get_object(*id)?),
+        object => Ok(object),
+    }
+}
+
+fn collect_images<'a>(doc: &'a Document, resources: &'a Dictionary, images: &mut Vec<&'a Stream>) {
+    let xobjects = match resources
+        .get(b"XObject")
+        .ok()
+        .and_then(|xobjects| resolve(doc, xobjects).ok())
+        .and_then(|xobjects| xobjects.as_dict().ok())
+    {
+        Some(xobjects) => xobjects,
+        None => return,
+    };
+
+    for (_, xobject) in xobjects.iter() {
+        let stream = match resolve(doc, xobject).ok().and_then(|o| o.as_stream().ok()) {
+            Some(stream) => stream,
+            None => continue,
+        };
+        if stream.dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Image".as_ref()) {
+            images.push(stream);
+        }
+    }
+}
+
+fn dimension(stream: &Stream, key: &[u8]) -> i64 {
+    stream.dict.get(key).and_then(Object::as_i64).unwrap_or(0)
+}
+
+fn filters(stream: &Stream) -> Vec<Vec<u8>> {
+    match stream.dict.get(b"Filter") {
+        Ok(Object::Name(name)) => vec![name.clone()],
+        Ok(Object::Array(filters)) => filters
+            .iter()
+            .filter_map(|filter| filter.as_name().ok())
+            .map(|name| name.to_vec())
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Apply `filters` of an image stream, lopdf refuses to decompress image streams
+/// directly so the filters are applied on a copy without `Subtype`
+fn decode(stream: &Stream, filters: &[Vec<u8>]) -> Result<Vec<u8>> {
+    if filters.is_empty() {
+        return Ok(stream.content.clone());
+    }
+
+    let mut dict = stream.dict.clone();
+    dict.remove(b"Subtype");
+    dict.set(
+        "Filter",
+        Object::Array(filters.iter().cloned().map(Object::Name).collect()),
+    );
+    Ok(Stream::new(dict, stream.content.clone()).decompressed_content()?)
+}
+
+fn encode_png(stream: &Stream) -> Result<Vec<u8>> {
+    let width = dimension(stream, b"Width") as u32;
+    let height = dimension(stream, b"Height") as u32;
+    let bits = dimension(stream, b"BitsPerComponent");
+    let color_type = match stream.dict.get(b"ColorSpace").and_then(Object::as_name) {
+        Ok(b"DeviceRGB") => png::ColorType::Rgb,
+        Ok(b"DeviceGray") => png::ColorType::Grayscale,
+        _ => return Err(anyhow!("unsupported image color space")),
+    };
+    if bits != 8 {
+        return Err(anyhow!("unsupported image bit depth {bits}"));
+    }
+
+    let data = decode(stream, &filters(stream))?;
+
+    let mut buf = vec![];
+    {
+        let mut encoder = png::Encoder::new(Cursor::new(&mut buf), width, height);
+        encoder.set_color(color_type);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&data)?;
+    }
+
+    Ok(buf)
+}
+
+/// Extract largest image of 1-based `page`, returns content type and data
+pub fn get_page_image(path: &Path, page: u32) -> Result<(String, Vec<u8>)> {
+    let doc = load(path)?;
+    let page_id = *doc
+        .get_pages()
+        .get(&page)
+        .ok_or_else(|| anyhow!("{} has no page {page}", path.display()))?;
+
+    let mut images = vec![];
+    let (resources, resource_ids) = doc.get_page_resources(page_id);
+    if let Some(resources) = resources {
+        collect_images(&doc, resources, &mut images);
+    }
+    for id in resource_ids {
+        if let Ok(resources) = doc.get_dictionary(id) {
+            collect_images(&doc, resources, &mut images);
+        }
+    }
+
+    let image = images
+        .into_iter()
+        .max_by_key(|stream| dimension(stream, b"Width") * dimension(stream, b"Height"))
+        .ok_or_else(|| anyhow!("page {page} of {} has no image", path.display()))?;
+
+    // encoded images can still be wrapped in other filters, e.g. `[/FlateDecode /DCTDecode]`
+    let mut filters = filters(image);
+    let content_type = match filters.last().map(Vec::as_slice) {
+        Some(b"DCTDecode") => "image/jpeg",
+        Some(b"JPXDecode") => "image/jp2",
+        _ => return Ok(("image/png".to_string(), encode_png(image)?)),
+    };
+    filters.pop();
+
+    Ok((content_type.to_string(), decode(image, &filters)?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lopdf::dictionary;
+
+    #[test]
+    fn test_get_page_image_nested_filters() {
+        let jpeg = [0xFF, 0xD8, 0xFF, 0xE0].repeat(256);
+
+        let mut image = Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => 10,
+                "Height" => 10,
+                "ColorSpace" => "DeviceRGB",
+                "BitsPerComponent" => 8,
+            },
+            jpeg.clone(),
+        );
+        image.compress().unwrap();
+        assert_ne!(image.content, jpeg);
+        image.dict.set(
+            "Filter",
+            Object::Array(vec!["FlateDecode".into(), "DCTDecode".into()]),
+        );
+
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let image_id = doc.add_object(image);
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Resources" => dictionary! {
+                "XObject" => dictionary! { "Im0" => image_id },
+            },
+        });
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        doc.trailer.set("Root", catalog_id);
+
+        let path = std::env::temp_dir().join(format!("tanoshi-pdf-{}.pdf", std::process::id()));
+        doc.save(&path).unwrap();
+
+        assert_eq!(get_pages(&path).unwrap().len(), 1);
+        let (content_type, data) = get_page_image(&path, 1).unwrap();
+        assert_eq!(content_type, "image/jpeg");
+        assert_eq!(data, jpeg);
+
+        // served from cache the second time
+        assert_eq!(get_page_image(&path, 1).unwrap().1, jpeg);
+        assert!(get_page_image(&path, 2).is_err());
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -1,13 +1,13 @@
 use super::{chapter::Chapter, common::Cursor, guard::AdminGuard};
 use crate::{
     domain::services::download::DownloadService,
-    infrastructure::{config::Config, domain::repositories::download::DownloadRepositoryImpl},
+    infrastructure::domain::repositories::download::DownloadRepositoryImpl,
 };
 use async_graphql::{
     connection::{query, Connection, Edge, EmptyFields},
     Context, Error, Object, Result, SimpleObject,
 };
-use chrono::Utc;
+use chrono::{NaiveTime, Utc};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
 #[derive(Debug, SimpleObject)]
@@ -21,6 +21,8 @@ pub struct DownloadQueueEntry {
     pub downloaded: i64,
     pub total: i64,
     pub priority: i64,
+    pub failed: bool,
+    pub error: Option<String>,
 }
 
 impl From<crate::domain::entities::download::DownloadQueueEntry> for DownloadQueueEntry {
@@ -35,6 +37,26 @@ impl From<crate::domain::entities::download::DownloadQueueEntry> for DownloadQue
             downloaded: queue.downloaded,
             total: queue.total,
             priority: queue.priority,
+            failed: queue.failed,
+            error: queue.error,
+        }
+    }
+}
+
+const TIME_FORMAT: &str = "%H:%M";
+
+/// Daily window in local time in which chapters are downloaded
+#[derive(Debug, SimpleObject)]
+pub struct DownloadSchedule {
+    pub start: String,
+    pub end: String,
+}
+
+impl From<crate::domain::entities::download::DownloadSchedule> for DownloadSchedule {
+    fn from(schedule: crate::domain::entities::download::DownloadSchedule) -> Self {
+        Self {
+            start: schedule.start.format(TIME_FORMAT).to_string(),
+            end: schedule.end.format(TIME_FORMAT).to_string(),
         }
     }
 }
@@ -44,16 +66,36 @@ pub struct DownloadRoot;
 
 #[Object]
 impl DownloadRoot {
+    /// False if downloads are paused or outside of their schedule
     async fn download_status(&self, ctx: &Context<'_>) -> Result<bool> {
-        let download_path = &ctx.data::<Config>()?.download_path;
-
         let status = ctx
             .data::<DownloadService<DownloadRepositoryImpl>>()?
-            .get_download_status(download_path);
+            .get_download_status()
+            .await?;
 
         Ok(status)
     }
 
+    /// True if downloads are paused by `pauseDownload`, regardless of schedule
+    async fn download_paused(&self, ctx: &Context<'_>) -> Result<bool> {
+        let paused = ctx
+            .data::<DownloadService<DownloadRepositoryImpl>>()?
+            .get_download_paused()
+            .await?;
+
+        Ok(paused)
+    }
+
+    async fn download_schedule(&self, ctx: &Context<'_>) -> Result<Option<DownloadSchedule>> {
+        let schedule = ctx
+            .data::<DownloadService<DownloadRepositoryImpl>>()?
+            .get_download_schedule()
+            .await?
+            .map(|schedule| schedule.into());
+
+        Ok(schedule)
+    }
+
     #[graphql(guard = "AdminGuard::new()")]
     async fn download_queue(&self, ctx: &Context<'_>) -> Result<Vec<DownloadQueueEntry>> {
         let queue = ctx
@@ -163,20 +205,48 @@ pub struct DownloadMutationRoot;
 #[Object]
 impl DownloadMutationRoot {
     async fn pause_download(&self, ctx: &Context<'_>) -> Result<bool> {
-        let download_path = &ctx.data::<Config>()?.download_path;
-
         ctx.data::<DownloadService<DownloadRepositoryImpl>>()?
-            .change_download_status(download_path, false)
+            .change_download_status(false)
             .await?;
 
         Ok(true)
     }
 
     async fn resume_download(&self, ctx: &Context<'_>) -> Result<bool> {
-        let download_path = &ctx.data::<Config>()?.download_path;
+        ctx.data::<DownloadService<DownloadRepositoryImpl>>()?
+            .change_download_status(true)
+            .await?;
+
+        Ok(true)
+    }
+
+    /// Only download between `start` and `end` local time, e.g. `01:00` and `07:00`,
+    /// any time if both are null
+    #[graphql(guard = "AdminGuard::new()")]
+    async fn set_download_schedule(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "start time as HH:MM")] start: Option<String>,
+        #[graphql(desc = "end time as HH:MM")] end: Option<String>,
+    ) -> Result<bool> {
+        let schedule = match (start, end) {
+            (Some(start), Some(end)) => {
+                let start = NaiveTime::parse_from_str(&start, TIME_FORMAT)?;
+                let end = NaiveTime::parse_from_str(&end, TIME_FORMAT)?;
+                if start == end {
+                    return Err(
+                        "start and end must be different, set both to null to download any time"
+                            .into(),
+                    );
+                }
+                Some(crate::domain::entities::download::DownloadSchedule { start, end })
+            }
+            (None, None) => None,
+            _ => return Err("start and end must be both set or both null".into()),
+        };
 
         ctx.data::<DownloadService<DownloadRepositoryImpl>>()?
-            .change_download_status(download_path, true)
+            .change_download_schedule(schedule)
             .await?;
 
         Ok(true)
@@ -202,6 +272,34 @@ impl DownloadMutationRoot {
         Ok(len)
     }
 
+    #[graphql(guard = "AdminGuard::new()")]
+    async fn retry_failed_downloads(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "chapter ids")] ids: Vec<i64>,
+    ) -> Result<i64> {
+        let len = ids.len() as i64;
+        ctx.data::<DownloadService<DownloadRepositoryImpl>>()?
+            .retry_failed_downloads(ids)
+            .await?;
+
+        Ok(len)
+    }
+
+    #[graphql(guard = "AdminGuard::new()")]
+    async fn discard_failed_downloads(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "chapter ids")] ids: Vec<i64>,
+    ) -> Result<i64> {
+        let len = ctx
+            .data::<DownloadService<DownloadRepositoryImpl>>()?
+            .discard_failed_downloads(ids)
+            .await?;
+
+        Ok(len as i64)
+    }
+
     #[graphql(guard = "AdminGuard::new()")]
     async fn remove_downloaded_chapters(&self, ctx: &Context<'_>, ids: Vec<i64>) -> Result<i64> {
         let len = ids.len() as i64;
@@ -212,6 +310,17 @@ impl DownloadMutationRoot {
         Ok(len)
     }
 
+    /// Verify downloaded chapters in background, broken ones are downloaded again and
+    /// replaced once complete
+    #[graphql(guard = "AdminGuard::new()")]
+    async fn scan_downloads(&self, ctx: &Context<'_>) -> Result<bool> {
+        ctx.data::<DownloadService<DownloadRepositoryImpl>>()?
+            .scan_downloads()
+            .await?;
+
+        Ok(true)
+    }
+
     #[graphql(guard = "AdminGuard::new()")]
     async fn update_chapter_priority(
         &self,
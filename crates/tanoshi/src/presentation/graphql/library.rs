@@ -1,6 +1,7 @@
 use super::{
     common::Cursor,
-    manga::Manga,
+    guard::AdminGuard,
+    manga::{LocalMangaInput, Manga},
     recent::{RecentChapter, RecentUpdate},
 };
 use crate::{
@@ -9,13 +10,14 @@ use crate::{
     },
     domain::services::{
         chapter::ChapterService, history::HistoryService, library::LibraryService,
-        tracker::TrackerService,
+        manga::MangaService, tracker::TrackerService,
     },
     infrastructure::{
         auth::Claims,
         domain::repositories::{
             chapter::ChapterRepositoryImpl, history::HistoryRepositoryImpl,
-            library::LibraryRepositoryImpl, tracker::TrackerRepositoryImpl,
+            library::LibraryRepositoryImpl, manga::MangaRepositoryImpl,
+            tracker::TrackerRepositoryImpl,
         },
     },
 };
@@ -322,6 +324,21 @@ impl LibraryMutationRoot {
         Ok(1)
     }
 
+    #[graphql(guard = "AdminGuard::new()")]
+    async fn update_local_manga(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "manga id")] manga_id: i64,
+        #[graphql(desc = "metadata to write")] input: LocalMangaInput,
+    ) -> Result<Manga> {
+        let manga = ctx
+            .data::<MangaService<MangaRepositoryImpl>>()?
+            .update_local_manga(manga_id, input.into())
+            .await?;
+
+        Ok(manga.into())
+    }
+
     async fn refresh_chapters(
         &self,
         ctx: &Context<'_>,
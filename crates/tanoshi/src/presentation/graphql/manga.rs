@@ -18,14 +18,40 @@ use crate::{
             image::ImageRepositoryImpl, image_cache::ImageCacheRepositoryImpl,
             source::SourceRepositoryImpl,
         },
+        local::LocalMangaInfo,
     },
     presentation::graphql::schema::DatabaseLoader,
 };
-use async_graphql::{dataloader::DataLoader, Context, Object, Result, SimpleObject};
+use async_graphql::{dataloader::DataLoader, Context, InputObject, Object, Result, SimpleObject};
 use chrono::NaiveDateTime;
 use rayon::prelude::*;
 use tanoshi_vm::extension::ExtensionManager;
 
+/// Metadata of a local manga, unset fields are left unchanged
+#[derive(Debug, Default, InputObject)]
+pub struct LocalMangaInput {
+    pub title: Option<String>,
+    pub author: Option<Vec<String>>,
+    pub genre: Option<Vec<String>>,
+    pub status: Option<String>,
+    pub description: Option<String>,
+    /// image path relative to the manga folder, or name of an image inside a cbz
+    pub cover_path: Option<String>,
+}
+
+impl From<LocalMangaInput> for LocalMangaInfo {
+    fn from(input: LocalMangaInput) -> Self {
+        Self {
+            title: input.title,
+            author: input.author,
+            genre: input.genre,
+            status: input.status,
+            description: input.description,
+            cover_path: input.cover_path,
+        }
+    }
+}
+
 #[derive(Debug, SimpleObject)]
 pub struct Tracker {
     pub tracker: String,
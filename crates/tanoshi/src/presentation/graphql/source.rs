@@ -2,7 +2,9 @@ use super::{common::InputList, guard::AdminGuard};
 use crate::{
     domain::services::source::SourceService,
     infrastructure::{
-        auth::Claims, config::Config, domain::repositories::source::SourceRepositoryImpl,
+        auth::Claims,
+        config::{Config, ReadingDirection},
+        domain::repositories::source::SourceRepositoryImpl,
     },
 };
 use async_graphql::{Context, Object, Result};
@@ -20,6 +22,8 @@ pub struct Source {
     pub icon: String,
     #[serde(default)]
     pub has_update: bool,
+    #[serde(default)]
+    pub repository: Option<String>,
 }
 
 impl From<crate::domain::entities::source::Source> for Source {
@@ -33,6 +37,7 @@ impl From<crate::domain::entities::source::Source> for Source {
             lib_version: s.lib_version,
             icon: s.icon,
             has_update: s.has_update,
+            repository: s.repository,
         }
     }
 }
@@ -63,6 +68,31 @@ impl Source {
         self.has_update
     }
 
+    /// Name of the repository the source is installed from or available in
+    async fn repository(&self) -> Option<String> {
+        self.repository.clone()
+    }
+
+    /// Versions kept for rollback, newest first, only for installed source
+    async fn versions(&self, ctx: &Context<'_>) -> Result<Vec<String>> {
+        let extensions = ctx.data::<ExtensionManager>()?;
+        if !extensions.exists(self.id).await? {
+            return Ok(vec![]);
+        }
+
+        Ok(extensions.get_versions(self.id).await?)
+    }
+
+    /// Reading direction hint of a local source
+    async fn reading_direction(&self, ctx: &Context<'_>) -> Result<Option<ReadingDirection>> {
+        let folders = ctx.data::<Config>()?.local_folders()?;
+
+        Ok(folders
+            .into_iter()
+            .find(|(id, _)| *id == self.id)
+            .and_then(|(_, folder)| folder.reading_direction))
+    }
+
     async fn filters(&self, ctx: &Context<'_>) -> Result<InputList> {
         let filters = ctx.data::<ExtensionManager>()?.filter_list(self.id)?;
 
@@ -88,11 +118,11 @@ impl SourceRoot {
     ) -> Result<Vec<Source>> {
         let _ = ctx.data::<Claims>()?;
 
-        let repo_url = &ctx.data::<Config>()?.extension_repository;
+        let repositories = &ctx.data::<Config>()?.extension_repositories;
 
         let sources = ctx
             .data::<SourceService<SourceRepositoryImpl>>()?
-            .get_installed_sources(repo_url, check_update)
+            .get_installed_sources(repositories, check_update)
             .await?
             .into_iter()
             .map(Source::from)
@@ -104,11 +134,11 @@ impl SourceRoot {
     async fn available_sources(&self, ctx: &Context<'_>) -> Result<Vec<Source>> {
         let _ = ctx.data::<Claims>()?;
 
-        let repo_url = &ctx.data::<Config>()?.extension_repository;
+        let repositories = &ctx.data::<Config>()?.extension_repositories;
 
         let sources = ctx
             .data::<SourceService<SourceRepositoryImpl>>()?
-            .get_available_sources(repo_url)
+            .get_available_sources(repositories)
             .await?
             .into_iter()
             .map(Source::from)
@@ -141,10 +171,10 @@ impl SourceMutationRoot {
             return Err("source installed, use updateSource to update".into());
         }
 
-        let repo_url = &ctx.data::<Config>()?.extension_repository;
+        let repositories = &ctx.data::<Config>()?.extension_repositories;
 
         ctx.data::<SourceService<SourceRepositoryImpl>>()?
-            .install_source(repo_url, source_id)
+            .install_source(repositories, source_id)
             .await?;
 
         Ok(source_id)
@@ -161,10 +191,24 @@ impl SourceMutationRoot {
 
     #[graphql(guard = "AdminGuard::new()")]
     async fn update_source(&self, ctx: &Context<'_>, source_id: i64) -> Result<i64> {
-        let repo_url = &ctx.data::<Config>()?.extension_repository;
+        let repositories = &ctx.data::<Config>()?.extension_repositories;
 
         ctx.data::<SourceService<SourceRepositoryImpl>>()?
-            .update_source(repo_url, source_id)
+            .update_source(repositories, source_id)
+            .await?;
+
+        Ok(source_id)
+    }
+
+    #[graphql(guard = "AdminGuard::new()")]
+    async fn rollback_source(
+        &self,
+        ctx: &Context<'_>,
+        source_id: i64,
+        version: String,
+    ) -> Result<i64> {
+        ctx.data::<SourceService<SourceRepositoryImpl>>()?
+            .rollback_source(source_id, &version)
             .await?;
 
         Ok(source_id)